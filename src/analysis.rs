@@ -0,0 +1,201 @@
+use crate::config::Config;
+use crate::gpm::Gpmap;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::Write;
+
+
+
+pub struct PhenoNet{
+    pub pids: Vec<u64>,                 // Phenotypes (in the order of the map's phenotype list)
+    pub ngeno: Vec<u64>,                // Number of genotypes of each phenotype
+    pub transitions: Vec<Vec<f64>>,     // Fraction of point mutations from genotypes of phenotype i that produce phenotype j
+}
+
+
+
+impl PhenoNet{
+    pub fn new(cfg: &Config, gpmap: &Gpmap) -> PhenoNet{
+        let pids = gpmap.get_pid_list().clone();
+        let npids = pids.len();
+        let mut ngeno: Vec<u64> = vec![0; npids];
+        let mut transitions: Vec<Vec<f64>> = vec![vec![0.0; npids]; npids];
+
+        // Every genotype mutates to its neighbours (the 4 on a grid with periodic boundaries) in proportion to its direction weights
+        let pidpos = gpmap.get_pid_positions();
+        for idx in 0..cfg.grid_x{
+            for idy in 0..cfg.grid_y{
+                let from = pidpos[gpmap.get_pid_at(idx,idy) as usize];
+                ngeno[from] += 1;
+
+                let weights = gpmap.get_dirweights_at(idx,idy);
+                let wsum: f64 = weights.iter().sum();
+                for (dir,weight) in weights.iter().enumerate(){
+                    let (nx,ny) = gpmap.get_neighbour(idx,idy,dir);
+                    let to = pidpos[gpmap.get_pid_at(nx,ny) as usize];
+                    transitions[from][to] += weight/wsum;
+                }
+            }
+        }
+
+        for (from,row) in transitions.iter_mut().enumerate(){
+            row.iter_mut().for_each(|t| *t /= ngeno[from] as f64);
+        }
+
+        PhenoNet { pids, ngeno, transitions }
+    }
+
+    pub fn get_robustness(&self, idx: usize) -> f64{
+        // Fraction of point mutations that keep the phenotype
+        self.transitions[idx][idx]
+    }
+
+    pub fn get_evolvability(&self, idx: usize) -> usize{
+        // Number of other phenotypes one point mutation away
+        self.transitions[idx].iter().enumerate().filter(|(to,t)| *to!=idx && **t>0.0).count()
+    }
+
+    pub fn write_files(&self, dir: &str) -> Result<(),Box<dyn Error>>{
+        let total: u64 = self.ngeno.iter().sum();
+
+        // Full transition matrix in long format
+        let mut netfile = File::create(format!("{}/phenonet.csv",dir))?;
+        netfile.write_all(b"from,to,fraction\n")?;
+        for (from,row) in self.transitions.iter().enumerate(){
+            for (to,fraction) in row.iter().enumerate(){
+                netfile.write_all(format!("{},{},{}\n",self.pids[from],self.pids[to],fraction).as_bytes())?;
+            }
+        }
+
+        let mut statsfile = File::create(format!("{}/phenostats.csv",dir))?;
+        statsfile.write_all(b"pid,ngeno,freq,robustness,evolvability\n")?;
+        for (idx,pid) in self.pids.iter().enumerate(){
+            statsfile.write_all(format!("{},{},{},{},{}\n",pid,self.ngeno[idx],self.ngeno[idx] as f64/total as f64,self.get_robustness(idx),self.get_evolvability(idx)).as_bytes())?;
+        }
+
+        // Directed graph between different phenotypes (GraphML, readable by networkx, igraph, Gephi and Cytoscape)
+        let mut graphfile = File::create(format!("{}/phenonet.graphml",dir))?;
+        graphfile.write_all(b"<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n")?;
+        graphfile.write_all(b"  <key id=\"ngeno\" for=\"node\" attr.name=\"ngeno\" attr.type=\"long\"/>\n")?;
+        graphfile.write_all(b"  <key id=\"robustness\" for=\"node\" attr.name=\"robustness\" attr.type=\"double\"/>\n")?;
+        graphfile.write_all(b"  <key id=\"evolvability\" for=\"node\" attr.name=\"evolvability\" attr.type=\"long\"/>\n")?;
+        graphfile.write_all(b"  <key id=\"fraction\" for=\"edge\" attr.name=\"fraction\" attr.type=\"double\"/>\n")?;
+        graphfile.write_all(b"  <graph id=\"phenonet\" edgedefault=\"directed\">\n")?;
+        for (idx,pid) in self.pids.iter().enumerate(){
+            graphfile.write_all(format!("    <node id=\"{}\"><data key=\"ngeno\">{}</data><data key=\"robustness\">{}</data><data key=\"evolvability\">{}</data></node>\n",
+                pid,self.ngeno[idx],self.get_robustness(idx),self.get_evolvability(idx)).as_bytes())?;
+        }
+        for (from,row) in self.transitions.iter().enumerate(){
+            for (to,fraction) in row.iter().enumerate(){
+                if from!=to && *fraction>0.0 {
+                    graphfile.write_all(format!("    <edge source=\"{}\" target=\"{}\"><data key=\"fraction\">{}</data></edge>\n",self.pids[from],self.pids[to],fraction).as_bytes())?;
+                }
+            }
+        }
+        graphfile.write_all(b"  </graph>\n</graphml>\n")?;
+
+        Ok(())
+    }
+}
+
+
+
+pub struct Components{
+    pub labels: Vec<Vec<usize>>,                // Component of each genotype (indexed by x and y)
+    pub pids: Vec<u64>,                         // Phenotype of each component
+    pub sizes: Vec<u64>,                        // Number of genotypes in each component
+    pub nboundary: Vec<u64>,                    // Number of genotypes in each component with a neighbour of another phenotype
+    pub touching: Vec<BTreeMap<u64,u64>>,       // Number of links from each component to genotypes of every other phenotype
+}
+
+
+
+impl Components{
+    pub fn new(cfg: &Config, gpmap: &Gpmap) -> Components{
+        let mut labels: Vec<Vec<Option<usize>>> = vec![vec![None; cfg.grid_y as usize]; cfg.grid_x as usize];
+        let mut pids: Vec<u64> = Vec::new();
+        let mut sizes: Vec<u64> = Vec::new();
+        let mut nboundary: Vec<u64> = Vec::new();
+        let mut touching: Vec<BTreeMap<u64,u64>> = Vec::new();
+
+        // Flood fill over neighbours of the same phenotype (in order of x then y)
+        for startx in 0..cfg.grid_x{
+            for starty in 0..cfg.grid_y{
+                if labels[startx as usize][starty as usize].is_some(){
+                    continue;
+                }
+                let comp = pids.len();
+                let pid = gpmap.get_pid_at(startx,starty);
+                pids.push(pid);
+                sizes.push(0);
+                nboundary.push(0);
+                touching.push(BTreeMap::new());
+
+                labels[startx as usize][starty as usize] = Some(comp);
+                let mut stack: Vec<(u64,u64)> = vec![(startx,starty)];
+                while let Some((x,y)) = stack.pop(){
+                    sizes[comp] += 1;
+                    let mut boundary = false;
+                    for dir in 0..gpmap.get_nneighbours(){
+                        if !is_linked(gpmap,x,y,dir){
+                            continue;
+                        }
+                        let (nx,ny) = gpmap.get_neighbour(x,y,dir);
+                        let npid = gpmap.get_pid_at(nx,ny);
+                        if npid!=pid {
+                            boundary = true;
+                            *touching[comp].entry(npid).or_insert(0) += 1;
+                        } else if labels[nx as usize][ny as usize].is_none() {
+                            labels[nx as usize][ny as usize] = Some(comp);
+                            stack.push((nx,ny));
+                        }
+                    }
+                    if boundary {
+                        nboundary[comp] += 1;
+                    }
+                }
+            }
+        }
+
+        let labels = labels.into_iter().map(|row| row.into_iter().map(|l| l.unwrap()).collect()).collect();
+        Components { labels, pids, sizes, nboundary, touching }
+    }
+
+    pub fn get_component_at(&self, x: u64, y: u64) -> usize{
+        self.labels[x as usize][y as usize]
+    }
+
+    pub fn write_files(&self, dir: &str) -> Result<(),Box<dyn Error>>{
+        let mut compfile = File::create(format!("{}/components.csv",dir))?;
+        compfile.write_all(b"component,pid,size,nboundary,ntouching\n")?;
+        for (comp,pid) in self.pids.iter().enumerate(){
+            compfile.write_all(format!("{},{},{},{},{}\n",comp,pid,self.sizes[comp],self.nboundary[comp],self.touching[comp].len()).as_bytes())?;
+        }
+
+        // Other phenotypes each component touches, with the number of mutational links to them
+        let mut touchfile = File::create(format!("{}/componenttouch.csv",dir))?;
+        touchfile.write_all(b"component,pid,touchpid,nlinks\n")?;
+        for (comp,pid) in self.pids.iter().enumerate(){
+            for (touchpid,nlinks) in self.touching[comp].iter(){
+                touchfile.write_all(format!("{},{},{},{}\n",comp,pid,touchpid,nlinks).as_bytes())?;
+            }
+        }
+
+        // Component labels laid out like the gp-map file (one row per y)
+        let mut mapfile = File::create(format!("{}/componentmap.csv",dir))?;
+        for idy in 0..self.labels[0].len(){
+            let row: Vec<String> = self.labels.iter().map(|col| col[idy].to_string()).collect();
+            mapfile.write_all(format!("{}\n",row.join(",")).as_bytes())?;
+        }
+
+        Ok(())
+    }
+}
+
+
+fn is_linked(gpmap: &Gpmap, x: u64, y: u64, dir: usize) -> bool{
+    // Neighbours are linked if a point mutation between them is possible in either direction
+    let (nx,ny) = gpmap.get_neighbour(x,y,dir);
+    gpmap.get_dirweight_at(x,y,dir)>0.0 || gpmap.get_dirweight_at(nx,ny,gpmap.get_reverse_dir(dir))>0.0
+}
@@ -18,32 +18,93 @@ pub struct Config {
 
     pub gpfilepath:  PathBuf, // Path to GP-map file
     pub envfilepath: PathBuf, // Path to Env file
+    pub biasfilepath: PathBuf, // Path to mutation bias file (empty if unused)
+    pub initfilepath: PathBuf, // Path to initial population file (used if initpop is "file")
+
+    // Built-in gp-maps
+    pub gpmtype: String, // Where the map comes from (file, or rna, hp or grn for a built-in sequence map)
+    pub seqlen: u64, // Length of the sequences of a built-in map
+    pub foldcachedir: PathBuf, // Directory of the fold enumeration caches of the hp map (one file per chain length)
+    pub ngenes: u64, // Number of genes of the grn map
+    pub grnweights: Vec<i64>, // Interaction weights a gene can have on another in the grn map
+    pub grninit: Vec<u64>, // Initial expression state (0 or 1 for every gene) of the grn map (only the first gene on if empty)
 
     // Evolutionary parameters
     pub popsize: u64, // Population Size
     pub mutprob: f64, // Probability of a genotype changing per generation
+    pub mutweights: Vec<f64>, // Relative weights of mutating north, east, south and west
+
+    // Mutation rate evolution parameters
+    pub evolmut: bool, // Should mutation rates evolve through heritable modifiers?
+    pub mutmin: f64, // Lowest mutation probability a modifier can confer
+    pub mutmax: f64, // Highest mutation probability a modifier can confer
+    pub mutlevels: u64, // Number of modifier levels (log-spaced between mutmin and mutmax)
+    pub mutmodprob: f64, // Probability of an offspring's modifier mutating to a neighbouring level
 
 
 
     // Simulation Parameters
+    pub nrepl: u64, // Number of replicates to run (with ids starting at replid)
+    pub mode: String, // Simulation mode ("wf" for Wright-Fisher, "meanfield" for the deterministic infinite population limit, "sswm" for origin-fixation dynamics, "invasion" for fixation of introduced mutants, "phenonet" and "components" to only analyse the map, "genmap" to only generate a map)
     pub maxgens: u64, // Maximum generations to run simulation for
     pub saveevery: u64, // Time interval between consecutive population saves
+    pub invadexy: Vec<u64>, // Genotype of the introduced mutants in the invasion mode
+    pub invadek: u64, // Number of introduced mutants in the invasion mode
+    pub onextinct: String, // What to do if the population goes extinct ("stop", "restart" or "fatal")
+
+    // Stopping conditions (besides maxgens)
+    pub stoppheno: i64, // Stop when this phenotype's frequency exceeds stopfreq (-1 to disable)
+    pub stopfreq: f64, // Frequency that stoppheno has to exceed (0 to stop on its first appearance)
+    pub stopfix: bool, // Stop when a single phenotype is fixed in the population?
+    pub stopstasis: u64, // Stop after this many generations without change in the dominant genotype (0 to disable)
+    pub stopswitches: u64, // Stop after this many environment switches (0 to disable)
+    pub stopwalltime: f64, // Stop after this many seconds of wall-clock time (0 to disable)
 
 
 
     // Output file options
     pub outdom: bool, // Output a file containing dominant genotypes?
+    pub outdomcomp: bool, // Also output the neutral component of the dominant genotype?
     pub outevol: bool, // Output a file containing evolvabilities?
     pub outenv: bool, // Output a file containing environment states?
     pub outaltmut: bool, // Output a file containing alternate mutation probabilities?
     pub outpopsave: bool, // Output population saves?
     pub outtimeavg:bool, // Output time-averages?
+    pub outfitness: bool, // Output a file containing the mean and variance of fitness and the genetic load?
+    pub outfitnessenv: bool, // Output a file containing the mean fitness of the population in every environment?
+    pub outdiversity: bool, // Output a file containing genotype and phenotype diversity statistics?
+    pub outspatial: bool, // Output a file containing the centroid and spread of the population on the grid?
+    pub outpheno: bool, // Output a file containing the abundance of each phenotype?
+    pub phenoevery: u64, // Time interval between phenotype abundance outputs (0 to use saveevery)
+    pub outlineage: bool, // Output the line of descent of the final dominant genotype?
+    pub outextinct: bool, // Output a file containing extinction events?
+    pub outsummary: bool, // Output a file containing the time and reason of stopping?
+    pub outinvasion: bool, // Output a file containing the fixation probability of introduced mutants? (only in the invasion mode)
+    pub outhitting: bool, // Output files containing phenotype hitting times?
+    pub hitfreq: f64, // Frequency threshold for the hitting times
+    pub outmutrate: bool, // Output the distribution of mutation rates? (only if mutation rates evolve)
 
     // Time-average options
     pub timeavgstart: u64, // Generation when to start time-averaging of population
     pub timeavgend: u64, // Generation when to end time-averaginf of population
 
 
+    // Initial population parameters
+    pub initpop: String, // Initial population strategy (uniform, randpheno, coords, unifpheno or file)
+    pub initxy: Vec<u64>, // Starting genotype if initpop is "coords"
+    pub initrepl: u64, // Replicate to read from the initial population file
+    pub inittime: i64, // Time to read from the initial population file (-1 for the last saved time)
+    pub initrescale: bool, // Rescale the initial population file to popsize instead of rejecting it?
+
+    // Map generator parameters (genmap mode)
+    pub maptype: String, // Family of the generated map (random, voronoi, stripes, checker, percolation or blobs)
+    pub mapnpheno: u64, // Number of phenotypes of the generated map
+    pub mapfreqs: Vec<f64>, // Relative frequencies of the phenotypes (random and voronoi, equal if empty)
+    pub mapwidth: u64, // Width of stripes and checker squares
+    pub mapcells: u64, // Number of voronoi cells
+    pub mapoccupancy: f64, // Probability of a genotype being occupied (percolation)
+    pub mapseed: i64, // Seed of the map generator (-1 for a random seed)
+
     // // Misc parameters
     pub rndstrtpheno: Vec<u64>, // start with population in a random genotypes belonging to a certain phenotype?
     pub replid: u64, // If using only one replicate should this replicate be assigned an ID? 
@@ -59,23 +120,79 @@ impl Default for Config{
             
             gpfilepath: PathBuf::from("./config/gpm.csv"),
             envfilepath: PathBuf::from("./config/env.cfg"),
+            biasfilepath: PathBuf::new(),
+            initfilepath: PathBuf::new(),
+
+            gpmtype: String::from("file"),
+            seqlen: 8,
+            foldcachedir: PathBuf::from("./config"),
+            ngenes: 3,
+            grnweights: vec![-1,0,1],
+            grninit: Vec::new(),
 
             popsize: 10000,
             mutprob: 0.01,
+            mutweights: vec![1.0,1.0,1.0,1.0],
 
+            evolmut: false,
+            mutmin: 0.0001,
+            mutmax: 0.1,
+            mutlevels: 13,
+            mutmodprob: 0.001,
+
+            nrepl: 1,
+            mode: String::from("wf"),
             maxgens: 1000000,
             saveevery: 1,
+            invadexy: vec![0,0],
+            invadek: 1,
+            onextinct: String::from("stop"),
+
+            stoppheno: -1,
+            stopfreq: 0.0,
+            stopfix: false,
+            stopstasis: 0,
+            stopswitches: 0,
+            stopwalltime: 0.0,
 
             outdom: true,
+            outdomcomp: false,
             outevol: true,
             outenv: true,
             outaltmut: true,
             outpopsave: true,
             outtimeavg: true,
+            outfitness: true,
+            outfitnessenv: false,
+            outdiversity: true,
+            outspatial: true,
+            outpheno: true,
+            phenoevery: 0,
+            outlineage: false,
+            outextinct: true,
+            outsummary: true,
+            outinvasion: true,
+            outhitting: false,
+            hitfreq: 0.5,
+            outmutrate: true,
 
             timeavgstart:0,
             timeavgend:1000000,
 
+            initpop: String::from("uniform"),
+            initxy: vec![0,0],
+            initrepl: 0,
+            inittime: -1,
+            initrescale: false,
+
+            maptype: String::from("random"),
+            mapnpheno: 2,
+            mapfreqs: Vec::new(),
+            mapwidth: 5,
+            mapcells: 10,
+            mapoccupancy: 0.5,
+            mapseed: -1,
+
             rndstrtpheno: vec![0,1], // Phenotype id 0 denotes inviable genotypes
             replid: 0,
             omega: 1.0
@@ -96,53 +213,252 @@ impl Config{
 
             ap.refer(&mut config.gpfilepath).add_option(&["-g","--gpfilepath"],Store,"Path to the genotype-phenotype map file");
             ap.refer(&mut config.envfilepath).add_option(&["-e","--envfilepath"],Store,"Path to the environment file");
+            ap.refer(&mut config.biasfilepath).add_option(&["--biasfilepath"],Store,"Path to a file with per-genotype mutation direction weights (rows of x,y,north,east,south,west)");
+            ap.refer(&mut config.gpmtype).add_option(&["--gpmtype"],Store,"Genotype-phenotype map: file (read from gpfilepath), rna (built-in map of RNA sequences folded into secondary structures), hp (built-in map of HP lattice proteins folded into compact structures) or grn (built-in map of gene regulatory networks and their attractors)");
+            ap.refer(&mut config.seqlen).add_option(&["--seqlen"],Store,"Length of the sequences of a built-in map (sets grid_x and grid_y)");
+            ap.refer(&mut config.foldcachedir).add_option(&["--foldcachedir"],Store,"Directory of the fold enumeration caches of the hp map");
+            ap.refer(&mut config.ngenes).add_option(&["--ngenes"],Store,"Number of genes of the grn map (sets seqlen to ngenes*ngenes)");
+            ap.refer(&mut config.grnweights).add_option(&["--grnweights"],List,"Interaction weights a gene can have on another in the grn map");
+            ap.refer(&mut config.grninit).add_option(&["--grninit"],List,"Initial expression state of the grn map (0 or 1 for every gene, only the first gene on if not given)");
 
             ap.refer(&mut config.popsize).add_option(&["-p","--popsize"],Store,"Population size");
             ap.refer(&mut config.mutprob).add_option(&["-m","--mutprob"],Store,"Mutation probability");
+            ap.refer(&mut config.mutweights).add_option(&["--mutweights"],List,"Relative weights of mutating north, east, south and west (4 values)");
+
+            ap.refer(&mut config.evolmut).add_option(&["--evolmut"],Store,"true/false, let mutation rates evolve through heritable modifiers (starting at the level closest to mutprob)?");
+            ap.refer(&mut config.mutmin).add_option(&["--mutmin"],Store,"Lowest mutation probability a modifier can confer");
+            ap.refer(&mut config.mutmax).add_option(&["--mutmax"],Store,"Highest mutation probability a modifier can confer");
+            ap.refer(&mut config.mutlevels).add_option(&["--mutlevels"],Store,"Number of modifier levels, log-spaced between mutmin and mutmax");
+            ap.refer(&mut config.mutmodprob).add_option(&["--mutmodprob"],Store,"Probability of an offspring's modifier mutating to a neighbouring level");
             
+            ap.refer(&mut config.mode).add_option(&["--mode"],Store,"Simulation mode: wf (Wright-Fisher), meanfield (deterministic selection-mutation recursion), sswm (strong-selection weak-mutation origin-fixation dynamics), invasion (Wright-Fisher runs until mutants introduced at invadexy are lost or fixed), phenonet (write the phenotype network of the map and exit), components (write the neutral components of the map and exit) or genmap (write a synthetic map and exit)");
+            ap.refer(&mut config.nrepl).add_option(&["--nrepl"],Store,"Number of replicates to run (with ids starting at replid), each from a fresh initial population");
             ap.refer(&mut config.maxgens).add_option(&["-t","--maxgens"],Store,"Maximum generations to run the simulation for");
             ap.refer(&mut config.saveevery).add_option(&["-s","--saveevery"],Store,"Time interval duration to create population saves (in generations)");
-            ap.refer(&mut config.outdom).add_option(&["-d","--outdom"],Store,"0/1, output file containing dominant genotypes?");
-            ap.refer(&mut config.outevol).add_option(&["-v","--outevol"],Store,"0/1, output file containing evolvabilities?");
-            ap.refer(&mut config.outenv).add_option(&["-n","--outenv"],Store,"0/1, output file containing environments?");
-            ap.refer(&mut config.outaltmut).add_option(&["-a","--outaltmut"],Store,"0/1, output file containing alternate mutant probabilities?");
-            ap.refer(&mut config.outpopsave).add_option(&["-o","--outpopsave"],Store,"0/1, output file containing population saves?");
-            ap.refer(&mut config.outtimeavg).add_option(&["--outtimeavg"],Store, "0/1, output file containing the time average?");
+            ap.refer(&mut config.invadexy).add_option(&["--invadexy"],List,"x and y of the mutant genotype introduced in the invasion mode");
+            ap.refer(&mut config.invadek).add_option(&["--invadek"],Store,"Number of mutants introduced in the invasion mode");
+            ap.refer(&mut config.onextinct).add_option(&["--onextinct"],Store,"If every occupied genotype has zero fitness: stop (record extinction and stop), restart (record extinction and restart from a fresh initial population) or fatal (exit with an error)");
+            ap.refer(&mut config.stoppheno).add_option(&["--stoppheno"],Store,"Stop when this phenotype's frequency exceeds stopfreq (-1 to disable)");
+            ap.refer(&mut config.stopfreq).add_option(&["--stopfreq"],Store,"Frequency that stoppheno has to exceed (0 to stop on its first appearance)");
+            ap.refer(&mut config.stopfix).add_option(&["--stopfix"],Store,"true/false, stop when a single phenotype is fixed in the population?");
+            ap.refer(&mut config.stopstasis).add_option(&["--stopstasis"],Store,"Stop after this many generations without change in the dominant genotype (0 to disable)");
+            ap.refer(&mut config.stopswitches).add_option(&["--stopswitches"],Store,"Stop after this many environment switches (0 to disable)");
+            ap.refer(&mut config.stopwalltime).add_option(&["--stopwalltime"],Store,"Stop after this many seconds of wall-clock time (0 to disable)");
+            ap.refer(&mut config.outdom).add_option(&["-d","--outdom"],Store,"true/false, output file containing dominant genotypes?");
+            ap.refer(&mut config.outdomcomp).add_option(&["--outdomcomp"],Store,"true/false, also output the neutral component of the dominant genotype?");
+            ap.refer(&mut config.outevol).add_option(&["-v","--outevol"],Store,"true/false, output file containing evolvabilities?");
+            ap.refer(&mut config.outenv).add_option(&["-n","--outenv"],Store,"true/false, output file containing environments?");
+            ap.refer(&mut config.outaltmut).add_option(&["-a","--outaltmut"],Store,"true/false, output file containing alternate mutant probabilities?");
+            ap.refer(&mut config.outpopsave).add_option(&["-o","--outpopsave"],Store,"true/false, output file containing population saves?");
+            ap.refer(&mut config.outtimeavg).add_option(&["--outtimeavg"],Store,"true/false, output file containing the time average?");
+            ap.refer(&mut config.outfitness).add_option(&["--outfitness"],Store,"true/false, output file containing the mean and variance of fitness and the genetic load?");
+            ap.refer(&mut config.outfitnessenv).add_option(&["--outfitnessenv"],Store,"true/false, output file containing the mean fitness the population would have in every environment?");
+            ap.refer(&mut config.outdiversity).add_option(&["--outdiversity"],Store,"true/false, output file containing genotype and phenotype diversity statistics?");
+            ap.refer(&mut config.outspatial).add_option(&["--outspatial"],Store,"true/false, output file containing the centroid and spread of the population on the genotype grid?");
+            ap.refer(&mut config.outpheno).add_option(&["--outpheno"],Store,"true/false, output file containing the abundance of each phenotype?");
+            ap.refer(&mut config.phenoevery).add_option(&["--phenoevery"],Store,"Time interval between phenotype abundance outputs (in generations, 0 to use saveevery)");
+            ap.refer(&mut config.outextinct).add_option(&["--outextinct"],Store,"true/false, output file containing extinction events?");
+            ap.refer(&mut config.outsummary).add_option(&["--outsummary"],Store,"true/false, output file containing the time and reason of stopping?");
+            ap.refer(&mut config.outinvasion).add_option(&["--outinvasion"],Store,"true/false, output file containing the fixation probability of introduced mutants? (only in the invasion mode)");
+            ap.refer(&mut config.outhitting).add_option(&["--outhitting"],Store,"true/false, output files containing the generation each phenotype is first produced and first reaches hitfreq?");
+            ap.refer(&mut config.hitfreq).add_option(&["--hitfreq"],Store,"Frequency threshold for the hitting times");
+            ap.refer(&mut config.outmutrate).add_option(&["--outmutrate"],Store,"true/false, output files containing the distribution of mutation rates? (only with --evolmut)");
+            ap.refer(&mut config.outlineage).add_option(&["--outlineage"],Store,"true/false, track lineages and output the line of descent of the final dominant genotype?");
 
             ap.refer(&mut config.timeavgstart).add_option(&["--timeavgstart"],Store,"Generation to start time-averaging at");
             ap.refer(&mut config.timeavgend).add_option(&["--timeavgend"],Store,"Generation to end time-averaging at");
 
-            ap.refer(&mut config.rndstrtpheno).add_option(&["-h","--rndstrtpheno"],List,"Start simulation with all organisms having a random genotype from given phenotypes?");
+            ap.refer(&mut config.initpop).add_option(&["--initpop"],Store,"Initial population: uniform (all genotypes), randpheno (everyone at one random genotype of rndstrtpheno), coords (everyone at initxy), unifpheno (uniform over genotypes of rndstrtpheno) or file (read from initfilepath)");
+            ap.refer(&mut config.initxy).add_option(&["--initxy"],List,"x and y of the starting genotype if initpop is coords");
+            ap.refer(&mut config.initfilepath).add_option(&["--initfilepath"],Store,"Path to the initial population file (rows of x,y,pop, or a population.csv from a previous run) if initpop is file");
+            ap.refer(&mut config.initrepl).add_option(&["--initrepl"],Store,"Replicate to read from the initial population file");
+            ap.refer(&mut config.inittime).add_option(&["--inittime"],Store,"Time to read from the initial population file (-1 for the last saved time)");
+            ap.refer(&mut config.initrescale).add_option(&["--initrescale"],Store,"true/false, rescale the initial population file to popsize instead of rejecting it?");
+            ap.refer(&mut config.maptype).add_option(&["--maptype"],Store,"Family of the map generated in the genmap mode: random, voronoi, stripes, checker, percolation or blobs");
+            ap.refer(&mut config.mapnpheno).add_option(&["--mapnpheno"],Store,"Number of phenotypes of the generated map (not used by percolation)");
+            ap.refer(&mut config.mapfreqs).add_option(&["--mapfreqs"],List,"Relative frequencies of the phenotypes of random and voronoi maps (equal if not given)");
+            ap.refer(&mut config.mapwidth).add_option(&["--mapwidth"],Store,"Width of stripes and checker squares");
+            ap.refer(&mut config.mapcells).add_option(&["--mapcells"],Store,"Number of cells of voronoi maps");
+            ap.refer(&mut config.mapoccupancy).add_option(&["--mapoccupancy"],Store,"Probability of a genotype being occupied in percolation maps");
+            ap.refer(&mut config.mapseed).add_option(&["--mapseed"],Store,"Seed of the map generator (-1 for a random seed)");
+            ap.refer(&mut config.rndstrtpheno).add_option(&["-h","--rndstrtpheno"],List,"Phenotypes to start from if initpop is randpheno or unifpheno");
             ap.refer(&mut config.replid).add_option(&["-l","--replid"],Store,"If only one replicate, should this replicate be given an id?");
             ap.refer(&mut config.omega).add_option(&["--omega"],Store,"Replace every -1 in env.cfg file with the value specified here (allows changing fluctuation rate without changing config file)");
 
 
             ap.parse_args_or_exit(); 
         }
+
+        config.set_sequence_grid()?;
+        config.validate()?;
         Ok(config)
     }
 
+    pub fn set_sequence_grid(&mut self) -> Result<(),Box<dyn Error>>{
+        // Built-in maps lay their sequences out on a grid: the first half of the sites gives x and the rest y
+        let maxlen = match self.gpmtype.as_str(){
+            "file" => return Ok(()),
+            "rna" => 10,
+            "hp" => 20,
+            "grn" => {
+                // Sites are the entries of the interaction matrix, each taking one of the weights (at most about a million networks)
+                if self.grnweights.len()<2 {
+                    return Err("grnweights should have at least 2 values".into());
+                }
+                self.seqlen = self.ngenes*self.ngenes;
+                let maxlen = (20.0/(self.get_nletters() as f64).log2()).floor() as u64;
+                if self.ngenes==0 || self.seqlen>maxlen {
+                    return Err(format!("ngenes should be at least 1 and give at most {} interactions with {} weights",maxlen,self.grnweights.len()).into());
+                }
+                maxlen
+            },
+            _ => return Err(format!("unknown gp-map type {}",self.gpmtype).into()),
+        };
+        if self.seqlen==0 || self.seqlen>maxlen {
+            return Err(format!("seqlen should be between 1 and {} for the {} map",maxlen,self.gpmtype).into());
+        }
+        let xsites = self.seqlen.div_ceil(2);
+        self.grid_x = self.get_nletters().pow(xsites as u32);
+        self.grid_y = self.get_nletters().pow((self.seqlen-xsites) as u32);
+        Ok(())
+    }
+
+    pub fn get_nletters(&self) -> u64{
+        // Alphabet size of the sequences of a built-in map
+        match self.gpmtype.as_str(){
+            "rna" => 4,
+            "hp" => 2,
+            "grn" => self.grnweights.len() as u64,
+            _ => 0,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(),Box<dyn Error>>{
+        let config = self;
+        if !["uniform","randpheno","coords","unifpheno","file"].contains(&config.initpop.as_str()){
+            return Err(format!("unknown initial population strategy {}",config.initpop).into());
+        }
+        if !(config.mutprob>=0.0 && config.mutprob<1.0) {
+            return Err("mutprob should be at least 0 and below 1".into());
+        }
+        if config.nrepl==0 {
+            return Err("nrepl should be at least 1".into());
+        }
+        if !["stop","restart","fatal"].contains(&config.onextinct.as_str()){
+            return Err(format!("unknown extinction behaviour {}",config.onextinct).into());
+        }
+        match config.mode.as_str(){
+            "wf" | "phenonet" | "components" => {},
+            "genmap" => {
+                if config.mapnpheno==0 || config.mapwidth==0 || config.mapcells==0 {
+                    return Err("mapnpheno, mapwidth and mapcells should be at least 1".into());
+                }
+            },
+            "meanfield" | "sswm" => {
+                if config.evolmut || config.outlineage {
+                    return Err(format!("evolmut and outlineage are not available in the {} mode",config.mode).into());
+                }
+            },
+            "invasion" => {
+                if config.evolmut {
+                    return Err("evolmut is not available in the invasion mode".into());
+                }
+                if config.invadexy.len()!=2 || config.invadexy[0]>=config.grid_x || config.invadexy[1]>=config.grid_y {
+                    return Err(format!("invadexy {:?} should be an x and y coordinate inside the grid",config.invadexy).into());
+                }
+                if config.invadek==0 || config.invadek>=config.popsize {
+                    return Err("invadek should be between 1 and popsize-1".into());
+                }
+            },
+            _ => return Err(format!("unknown mode {}",config.mode).into()),
+        }
+        if config.gpmtype=="grn" && !config.grninit.is_empty() && (config.grninit.len()!=config.ngenes as usize || config.grninit.iter().any(|on| *on>1)) {
+            return Err("grninit should give a 0 or 1 for every gene".into());
+        }
+        if config.gpmtype!="file" && !config.biasfilepath.as_os_str().is_empty() {
+            return Err("a bias file can only be used with a gp-map file".into());
+        }
+        if config.mutweights.len()!=4 || config.mutweights.iter().any(|w| *w<0.0) || config.mutweights.iter().sum::<f64>()<=0.0 {
+            return Err("mutweights should be 4 non-negative values with a positive sum".into());
+        }
+        if config.evolmut && !(config.mutmin>0.0 && config.mutmin<=config.mutmax && config.mutmax<1.0){
+            return Err("mutation rate bounds must satisfy 0 < mutmin <= mutmax < 1".into());
+        }
+        Ok(())
+    }
+
 }
 
 impl fmt::Display for Config{
     fn fmt(&self, f:&mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f,"--grid_x {} --grid_y {} --gpfilepath {:?} --envfilepath {:?} --popsize {} --mutprob {} --maxgens {} --saveevery {}  --outdom {} --outevol {} --outenv {} --outaltmut {} --outpopsave  {} --outtimeavg {} --timeavgstart {} --timeavgend {} --rndstrtpheno {:?} --replid {}",
+        write!(f,"--grid_x {} --grid_y {} --gpfilepath {:?} --envfilepath {:?} --biasfilepath {:?} --gpmtype {} --seqlen {} --foldcachedir {:?} --ngenes {} --grnweights {:?} --grninit {:?} --popsize {} --mutprob {} --mutweights {:?} --evolmut {} --mutmin {} --mutmax {} --mutlevels {} --mutmodprob {} --nrepl {} --mode {} --maxgens {} --saveevery {} --invadexy {:?} --invadek {} --onextinct {} --stoppheno {} --stopfreq {} --stopfix {} --stopstasis {} --stopswitches {} --stopwalltime {}  --outdom {} --outdomcomp {} --outevol {} --outenv {} --outaltmut {} --outpopsave  {} --outtimeavg {} --outfitness {} --outfitnessenv {} --outdiversity {} --outspatial {} --outpheno {} --phenoevery {} --outlineage {} --outextinct {} --outsummary {} --outinvasion {} --outhitting {} --hitfreq {} --outmutrate {} --timeavgstart {} --timeavgend {} --initpop {} --initxy {:?} --initfilepath {:?} --initrepl {} --inittime {} --initrescale {} --maptype {} --mapnpheno {} --mapfreqs {:?} --mapwidth {} --mapcells {} --mapoccupancy {} --mapseed {} --rndstrtpheno {:?} --replid {}",
             self.grid_x,
             self.grid_y,
             self.gpfilepath,
             self.envfilepath,
+            self.biasfilepath,
+            self.gpmtype,
+            self.seqlen,
+            self.foldcachedir,
+            self.ngenes,
+            self.grnweights,
+            self.grninit,
             self.popsize,
             self.mutprob,
+            self.mutweights,
+            self.evolmut,
+            self.mutmin,
+            self.mutmax,
+            self.mutlevels,
+            self.mutmodprob,
+            self.nrepl,
+            self.mode,
             self.maxgens,
             self.saveevery,
+            self.invadexy,
+            self.invadek,
+            self.onextinct,
+            self.stoppheno,
+            self.stopfreq,
+            self.stopfix,
+            self.stopstasis,
+            self.stopswitches,
+            self.stopwalltime,
             self.outdom,
+            self.outdomcomp,
             self.outevol,
             self.outenv,
             self.outaltmut,
             self.outpopsave,
             self.outtimeavg,
+            self.outfitness,
+            self.outfitnessenv,
+            self.outdiversity,
+            self.outspatial,
+            self.outpheno,
+            self.phenoevery,
+            self.outlineage,
+            self.outextinct,
+            self.outsummary,
+            self.outinvasion,
+            self.outhitting,
+            self.hitfreq,
+            self.outmutrate,
             self.timeavgstart,
             self.timeavgend,
+            self.initpop,
+            self.initxy,
+            self.initfilepath,
+            self.initrepl,
+            self.inittime,
+            self.initrescale,
+            self.maptype,
+            self.mapnpheno,
+            self.mapfreqs,
+            self.mapwidth,
+            self.mapcells,
+            self.mapoccupancy,
+            self.mapseed,
             self.rndstrtpheno,
             self.replid    
         )
@@ -15,6 +15,7 @@ pub struct Environment{
     pfmaps: Vec<Vec<f64>>,     // Phenotype fitness maps (first index is envid, second index is pid)
     _cyctimes: Option<Vec<u64>>,        // Cycling times (might be null)
     weights: Option<Vec<f64>>,         // Probabilities (might be null)
+    pfpaths: Vec<PathBuf>,             // Phenotype fitness files the environment was read from (empty if built in memory)
 }
 
 impl Environment{
@@ -36,7 +37,7 @@ impl Environment{
         for (idx,line) in envcfgcontent.lines().enumerate(){
             let line_as_vec : Vec<&str> = line.split(" ").collect();
 
-            match &line_as_vec[0][..]{
+            match line_as_vec[0]{
                 "define" => {
                     let key = line_as_vec[1];
                     let fpath = PathBuf::from(line_as_vec[2]);
@@ -61,13 +62,6 @@ impl Environment{
         }
 
 
-        // Copy pffiles to output directory
-        for (_k,v) in &pfpaths{
-            let pffname = v.file_name().unwrap().to_str().unwrap();
-            fs::copy(v,format!("./output/{pffname}")).unwrap();
-        }
-
-
         // Parse env regime definition based on type
         let startidx = typelineidx.unwrap();
         let endidx = endlineidx.unwrap();
@@ -158,21 +152,35 @@ impl Environment{
         // Convert to struct and return
         let env = Environment { 
             regimetype: regimetype.unwrap(), 
-            pfmaps: pfmaps, 
+            pfmaps, 
             _cyctimes: cyctimes, 
-            weights: weights
+            weights,
+            pfpaths: pfpaths.into_values().collect(),
         };
 
         Ok(env)
     }
 
+    pub fn constant(pfmap: Vec<f64>) -> Environment{
+        // A single phenotype fitness map (indexed by pid)
+        Environment { regimetype: EnvRegimeType::Constant, pfmaps: vec![pfmap], _cyctimes: None, weights: None, pfpaths: Vec::new() }
+    }
+
+    pub fn switching(pfmaps: Vec<Vec<f64>>, switchprobs: Vec<f64>) -> Result<Environment,Box<dyn Error>>{
+        // Environments switch cyclically, leaving environment i with probability switchprobs[i] each generation
+        if pfmaps.is_empty() || pfmaps.len()!=switchprobs.len() {
+            return Err("a switching environment needs one switching probability per phenotype fitness map".into());
+        }
+        Ok(Environment { regimetype: EnvRegimeType::ProbSwitch, pfmaps, _cyctimes: None, weights: Some(switchprobs), pfpaths: Vec::new() })
+    }
+
     pub fn get_envid(&self,_time: u64,last_envid: i32)-> i32{
         match self.regimetype{
 
 
             // Constant environment
             EnvRegimeType::Constant => {
-                return 0
+                0
             },
 
 
@@ -186,7 +194,6 @@ impl Environment{
 
             // Probabilistically switching environment
             EnvRegimeType::ProbSwitch => {
-                let new_envid:i32;
                 if last_envid!=-1{
                     let switch_prob = self.weights.as_ref().unwrap()[last_envid as usize];
                     let next_envid = if last_envid<(self.weights.as_ref().unwrap().len() as i32 -1){
@@ -195,7 +202,7 @@ impl Environment{
                         0
                     };
                     
-                    new_envid = if switch_prob>rand::thread_rng().gen::<f64>() {
+                    if switch_prob>rand::thread_rng().gen::<f64>() {
                         next_envid
                     } else{
                         last_envid
@@ -203,16 +210,23 @@ impl Environment{
 
                 }
                 else{ // If no envid chose, sample randomly
-                    new_envid = rand::thread_rng().gen_range(0..self.weights.as_ref().unwrap().len() as i32);
+                    rand::thread_rng().gen_range(0..self.weights.as_ref().unwrap().len() as i32)
                 }
-                return new_envid
             }
         }
         
     }
 
     pub fn get_pfmap(&self,envid: i32) -> &Vec<f64> {
-        return &self.pfmaps[envid as usize];
+        &self.pfmaps[envid as usize]
+    }
+
+    pub fn get_pfmaps(&self) -> &Vec<Vec<f64>> {
+        &self.pfmaps
+    }
+
+    pub fn get_pfpaths(&self) -> &Vec<PathBuf> {
+        &self.pfpaths
     }
 }
 
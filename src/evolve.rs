@@ -1,147 +1,708 @@
 
 use crate::config::Config;
-use crate::output::Output;
+use crate::output::{self,Output};
 use crate::environment::Environment;
 use crate::gpm::Gpmap;
 use crate::pop::Population;
+use crate::lineage::Lineage;
+use crate::hitting::{self,HittingTimes};
+use crate::observer::{Observer,Observation,Record};
+use std::collections::HashMap;
 use std::error::Error;
+use std::time::Instant;
 
 use rand::prelude::*;
 use rand::distributions::WeightedIndex;
-use statrs::distribution::Poisson;
+use statrs::distribution::{Poisson,Discrete};
 
 
 
 
+pub struct RunSummary{
+    pub replid: u64,                    // Replicate id of the run
+    pub stoptime: u64,                  // Generation at which the run stopped
+    pub stopreason: String,             // Why the run stopped (maxgens, extinction or a stopping condition)
+    pub startenvid: i32,                // Environment at the start of the run
+    pub nswitches: u64,                 // Number of environment changes during the run
+    pub hitting: Option<HittingTimes>,  // Hitting times of every phenotype (if tracked)
+}
+
+
+struct RunState{
+    time: u64,                                  // Current generation
+    envid: i32,                                 // Current environment
+    startenvid: i32,                            // Environment at the start of the run
+    gfmap: Vec<Vec<f64>>,                       // Fitness of every genotype in the current environment
+    pop: Population,
+    lineage: Option<Lineage>,                   // Lineages (if tracked)
+    mutrate_by_env: HashMap<i32,(f64,u64)>,     // Summed mean mutation rate and generations spent in each environment
+    sswm_wait: Option<f64>,                     // Generations left until the next fixation in the sswm mode
+    starttime: Instant,                         // Wall-clock time at the start of the run
+    nswitches: u64,                             // Number of environment changes so far
+    lastdom: (usize,usize,f64),                 // Dominant genotype in the last generation
+    domstasis: u64,                             // Generations since the dominant genotype last changed
+    hittimes: Option<HittingTimes>,             // Hitting times (if tracked)
+}
+
+
 pub struct Simulation{
     config: Config,
     output: Output,
     env: Environment,
     gpmap: Gpmap,
     initpop: Population,
+    observers: Vec<Box<dyn Observer>>,
+    state: Option<RunState>, // Run in progress (None between runs)
+    regenerate: bool, // Draw a new initial population from the config for every replicate and restart (or reuse initpop)?
+    verbose: bool, // Print the progress of runs?
+    records: Option<Vec<Record>>, // Results of every output generation (if recorded)
 }
 
 
 impl Simulation{
     pub fn setup(cfg: Config,output: Output,env: Environment,gpm: Gpmap,pop: Population) -> Result<Simulation,Box<dyn Error>>{
-        Ok(Simulation {config:cfg, output:output, env:env, gpmap:gpm, initpop:pop })
+        check_pfmaps(&env,&gpm)?;
+        let observers = output::builtin_observers(&cfg,&gpm)?; // Per-generation output files
+        Ok(Simulation {config:cfg, output, env, gpmap:gpm, initpop:pop, observers, state: None, regenerate: true, verbose: true, records: None })
     }
 
-    pub fn run(&self){
+    pub fn add_observer(&mut self, observer: Box<dyn Observer>){
+        self.observers.push(observer);
+    }
+
+    pub fn set_verbose(&mut self, verbose: bool){
+        self.verbose = verbose;
+    }
+
+    pub fn set_recording(&mut self, recording: bool){
+        // Keep the results of every output generation in memory (see get_records)
+        self.records = if recording { Some(Vec::new()) } else { None };
+    }
+
+    pub fn get_records(&self) -> &[Record]{
+        self.records.as_deref().unwrap_or(&[])
+    }
+
+    pub fn take_records(&mut self) -> Vec<Record>{
+        // Recorded results so far, leaving the record empty
+        self.records.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    pub fn run_replicates(&mut self) -> Result<Vec<RunSummary>,Box<dyn Error>>{
+        // Run nrepl replicates (with ids starting at replid), each from a freshly generated initial population
+        // (or from the given one for simulations built with SimulationBuilder)
+        let firstrepl = self.config.replid;
+        let mut summaries: Vec<RunSummary> = Vec::new();
+
+        for repl in 0..self.config.nrepl{
+            self.config.replid = firstrepl+repl;
+            if repl>0 && self.regenerate {
+                self.initpop = Population::generate(&self.config,&self.gpmap)?;
+            }
+            summaries.push(self.run()?);
+        }
+        self.config.replid = firstrepl;
+
+        // Hitting time distributions across replicates
+        if self.output.hittingsummaryfile.is_some(){
+            let hittimes: Vec<HittingTimes> = summaries.iter().filter_map(|s| s.hitting.clone()).collect();
+            self.output.write_hittingsummaryfile(&hitting::summarize(&hittimes));
+        }
+
+        // Fixation probability of the introduced mutants
+        if self.output.invasionfile.is_some(){
+            self.output.write_invasionfile(&self.config,&self.gpmap,&summaries);
+        }
+
+        Ok(summaries)
+    }
 
-        let mut envid = self.env.get_envid(0,-1); // Get environment at start
-        let mut pfmap = self.env.get_pfmap(envid); // Get pffmap being used at start
-        let mut gfmap = self.gpmap.get_gfmap(pfmap); // Get gfmap being used at the start
+    pub fn run(&mut self) -> Result<RunSummary,Box<dyn Error>>{
+        // Step through generations until the run stops
+        self.report("Starting run...");
+        loop {
+            if let Some(summary) = self.step()? {
+                if summary.stopreason=="maxgens" {
+                    self.report("Finished succesfully!");
+                } else {
+                    self.report(&format!("Stopped at generation {} ({})",summary.stoptime,summary.stopreason));
+                }
+                return Ok(summary);
+            }
+        }
+    }
+
+    pub fn step(&mut self) -> Result<Option<RunSummary>,Box<dyn Error>>{
+        // Advance the current run by one generation (starting a run from the initial population if none is in progress)
+        // and return its summary once it stops
+        // Observers and the run state are moved out of the simulation for the duration of the step
+        let mut observers = std::mem::take(&mut self.observers);
+        let result = self.step_observed(&mut observers);
+        self.observers = observers;
+        result
+    }
+
+    fn step_observed(&mut self, observers: &mut [Box<dyn Observer>]) -> Result<Option<RunSummary>,Box<dyn Error>>{
+        let mut state = match self.state.take(){
+            Some(state) => state,
+            None => {
+                let state = self.start_run()?;
+                self.notify(observers,true,false,&state); // Generate an output
+                state
+            }
+        };
+
+        let stopreason = if state.time>=self.config.maxgens {
+            Some(String::from("maxgens"))
+        } else {
+            self.advance(&mut state,observers)?
+        };
+
+        match stopreason {
+            Some(reason) => Ok(Some(self.finish_run(state,reason))),
+            None => {
+                self.state = Some(state);
+                Ok(None)
+            }
+        }
+    }
+
+    fn start_run(&self) -> Result<RunState,Box<dyn Error>>{
+        let envid = self.env.get_envid(0,-1); // Get environment at start
+        let pfmap = self.env.get_pfmap(envid); // Get pffmap being used at start
+        let gfmap = self.gpmap.get_gfmap(pfmap); // Get gfmap being used at the start
         let mut pop = self.initpop.clone(); // Make a copy of the initial population
-        self.output.generate_output(0,&self.config,envid,&pop,&self.gpmap); // Generate an output
+        self.prepare_pop(&mut pop)?;
+        let lineage = if self.config.outlineage { Some(Lineage::new(&pop)) } else { None }; // Track lineages if required
+        let lastdom = pop.get_dominance_info();
+
+        let mut hittimes = if self.config.outhitting { Some(HittingTimes::new(&self.gpmap)) } else { None }; // Track hitting times if required
+        if let Some(hittimes) = hittimes.as_mut(){
+            hittimes.update(0,&pop,&self.gpmap,self.config.hitfreq);
+        }
+
+        Ok(RunState {
+            time: 0,
+            envid,
+            startenvid: envid,
+            gfmap,
+            pop,
+            lineage,
+            mutrate_by_env: HashMap::new(),
+            sswm_wait: None,
+            starttime: Instant::now(),
+            nswitches: 0,
+            lastdom,
+            domstasis: 0,
+            hittimes,
+        })
+    }
+
+    fn advance(&mut self, state: &mut RunState, observers: &mut [Box<dyn Observer>]) -> Result<Option<String>,Box<dyn Error>>{
+        state.time += 1;
+        let time = state.time;
+
+        // Note: Population selects on environment from last generation to create this generation!
+
+        // SELECTION AND MUTATION
+        let survived = if self.config.mode=="meanfield" {
+            self.select_mutate_meanfield(&mut state.pop,&state.gfmap) // Deterministic selection and mutation of abundances
+        } else if self.config.mode=="sswm" {
+            self.select_mutate_sswm(&mut state.pop,&state.gfmap,&mut state.sswm_wait) // Fixation of single mutants in a monomorphic population
+        } else {
+            self.select_mutate(&mut state.pop,&state.gfmap,state.lineage.as_mut(),time) // Perform selection and mutation on population using pfmap
+        };
+
+        // EXTINCTION (no occupied genotype has a non-zero fitness)
+        if !survived {
+            if self.output.extinctfile.is_some(){
+                self.output.write_extinctfile(&self.config,time,state.envid);
+            }
+            match self.config.onextinct.as_str(){
+                "stop" => {
+                    self.notify(observers,true,true,state); // Final outputs show the last population before extinction
+                    return Ok(Some(String::from("extinction")));
+                },
+                "restart" => {
+                    self.report(&format!("Population went extinct at generation {}, restarting.",time));
+                    state.pop = if self.regenerate { Population::generate(&self.config,&self.gpmap)? } else { self.initpop.clone() };
+                    self.prepare_pop(&mut state.pop)?;
+                    state.lineage = if self.config.outlineage { Some(Lineage::new(&state.pop)) } else { None };
+                    state.sswm_wait = None;
+                },
+                _ => {
+                    return Err(format!("population went extinct at generation {}: every occupied genotype has zero fitness in environment {}",time,state.envid).into());
+                }
+            }
+        }
+
+        state.pop.add_to_average(&self.config,time); // Add new population to population average (checked if required using time)
+        if let Some(hittimes) = state.hittimes.as_mut(){
+            hittimes.update(time,&state.pop,&self.gpmap,self.config.hitfreq);
+        }
+        if state.pop.evolving_mutrates(){
+            let entry = state.mutrate_by_env.entry(state.envid).or_insert((0.0,0));
+            entry.0 += state.pop.get_mean_mutrate(); // Population was selected in envid
+            entry.1 += 1;
+        }
 
-        println!("Starting run...");
+        // UPDATE ENVIRONMENT
+        let lastenvid = state.envid;
+        state.envid = self.env.get_envid(time,state.envid); // Get environment from env
+        if state.envid!=lastenvid {
+            state.sswm_wait = None; // Waiting times are memoryless, so redraw them under the new environment
+            state.nswitches += 1;
+        }
+        let pfmap = self.env.get_pfmap(state.envid); // Get pffmap
+        state.gfmap = self.gpmap.get_gfmap(pfmap);
+
+        // STOPPING CONDITIONS
+        let dom = state.pop.get_dominance_info();
+        if (dom.0,dom.1)==(state.lastdom.0,state.lastdom.1) {
+            state.domstasis += 1;
+        } else {
+            state.domstasis = 0;
+        }
+        state.lastdom = dom;
+        let mut stop = self.get_stop_reason(&state.pop,state.nswitches,state.domstasis,&state.starttime);
+        if stop.is_none() && time==self.config.maxgens {
+            stop = Some(String::from("maxgens"));
+        }
 
-        for time in 1..(self.config.maxgens+1){
+        // OUTPUTS (every saveevery generations and at the final generation)
+        let outtime = time.is_multiple_of(self.config.saveevery) || stop.is_some();
+        self.notify(observers,outtime,stop.is_some(),state);
 
-            // Note: Population selects on environment from last generation to create this generation!
+        Ok(stop)
+    }
 
-            // SELECTION AND MUTATION
-            self.select_mutate(&mut pop,&gfmap); // Perform selection and mutation on population using pfmap
-            pop.add_to_average(&self.config,time); // Add new population to population average (checked if required using time)
+    fn finish_run(&self, state: RunState, stopreason: String) -> RunSummary{
+        let RunState { time: stoptime, startenvid, pop, lineage, mutrate_by_env, mut hittimes, nswitches, .. } = state;
 
-            // UPDATE ENVIRONMENT
-            envid = self.env.get_envid(time,envid); // Get environment from env
-            pfmap = self.env.get_pfmap(envid); // Get pffmap
-            gfmap = self.gpmap.get_gfmap(pfmap);
+        // Write why and when the run stopped
+        if self.output.summaryfile.is_some(){
+            self.output.write_summaryfile(&self.config,stoptime,&stopreason);
+        }
 
-            // OUTPUTS
-            let mut outflag = false;
-            if time%self.config.saveevery==0 {
-                self.output.generate_output(time,&self.config,envid,&pop,&self.gpmap);
-                outflag = true;
+        // Write the hitting times of this replicate (censored at the stopping time)
+        if let Some(hittimes) = hittimes.as_mut(){
+            hittimes.endtime = stoptime;
+            if self.output.hittingfile.is_some(){
+                self.output.write_hittingfile(&self.config,hittimes);
             }
-            // Output if not saved by saveevery but is final generation
-            if time==self.config.maxgens && !outflag {
-                self.output.generate_output(time,&self.config,envid,&pop,&self.gpmap);
+        }
+
+        // Write the mean mutation rate in each environment
+        if self.output.mutrateenvfile.is_some(){
+            self.output.write_mutrateenvfile(&self.config,&mutrate_by_env);
+        }
+
+        // Write the line of descent of the final dominant genotype
+        if let Some(lineage) = lineage.as_ref(){
+            if self.output.lineagefile.is_some(){
+                self.output.write_lineagefile(&self.config,lineage,&pop,&self.gpmap);
             }
         }
-        println!("Finished succesfully!");
+        RunSummary { replid: self.config.replid, stoptime, stopreason, startenvid, nswitches, hitting: hittimes }
     }
 
-    pub fn select_mutate(&self,pop: &mut Population,gfmap: &Vec<Vec<f64>>){
-        let mut gtypes_in_pop:Vec<(u64,u64)> = Vec::new();
-        let mut weights: Vec<f64> = Vec::new();
+    pub fn get_population(&self) -> Option<&Population>{
+        // Population of the run in progress (None between runs)
+        self.state.as_ref().map(|state| &state.pop)
+    }
 
+    pub fn get_time(&self) -> Option<u64>{
+        self.state.as_ref().map(|state| state.time)
+    }
 
-        for (idx,row) in gfmap.iter().enumerate(){
-            for (idy,fitness) in row.iter().enumerate(){
-                let pop_at_xy = pop.get_at(idx as u64, idy as u64);
-                if pop_at_xy!=0{
-                    gtypes_in_pop.push((idx as u64,idy as u64));
-                    weights.push(*fitness*(pop_at_xy as f64));
+    pub fn get_envid(&self) -> Option<i32>{
+        self.state.as_ref().map(|state| state.envid)
+    }
+
+    fn notify(&mut self, observers: &mut [Box<dyn Observer>], outtime: bool, last: bool, state: &RunState){
+        let obs = Observation { config: &self.config, time: state.time, last, envid: state.envid, env: &self.env, pop: &state.pop, gpmap: &self.gpmap, gfmap: &state.gfmap };
+        for observer in observers.iter_mut(){
+            if outtime || observer.every_generation(){
+                observer.observe(&obs);
+            }
+        }
+        if outtime {
+            if let Some(records) = self.records.as_mut(){
+                records.push(Record::from_observation(&obs));
+            }
+        }
+    }
+
+    fn report(&self, message: &str){
+        if self.verbose {
+            println!("{}",message);
+        }
+    }
+
+    fn get_stop_reason(&self,pop: &Population,nswitches: u64,domstasis: u64,starttime: &Instant) -> Option<String>{
+        // Loss or fixation of the introduced mutant phenotype
+        if self.config.mode=="invasion" {
+            let mutpid = self.gpmap.get_pid_at(self.config.invadexy[0],self.config.invadexy[1]);
+            let phenocounts = pop.get_pheno_counts(&self.gpmap);
+            let total: f64 = phenocounts.iter().map(|(_,c)| c).sum();
+            let (_,mutcount) = phenocounts.iter().find(|(pid,_)| *pid==mutpid).unwrap();
+            if *mutcount==0.0 {
+                return Some(String::from("lost"));
+            }
+            if *mutcount==total {
+                return Some(String::from("fixed"));
+            }
+        }
+
+        // Phenotype above the target frequency
+        if self.config.stoppheno>=0 || self.config.stopfix {
+            let phenocounts = pop.get_pheno_counts(&self.gpmap);
+            let total: f64 = phenocounts.iter().map(|(_,c)| c).sum();
+
+            if let Some((_,count)) = phenocounts.iter().find(|(pid,_)| *pid as i64==self.config.stoppheno){
+                if count/total>self.config.stopfreq {
+                    return Some(String::from("pheno"));
                 }
             }
+            if self.config.stopfix && phenocounts.iter().any(|(_,c)| *c==total) {
+                return Some(String::from("fixation"));
+            }
+        }
+
+        // No change in the dominant genotype
+        if self.config.stopstasis>0 && domstasis>=self.config.stopstasis {
+            return Some(String::from("stasis"));
+        }
+
+        // Number of environment switches
+        if self.config.stopswitches>0 && nswitches>=self.config.stopswitches {
+            return Some(String::from("switches"));
+        }
+
+        // Wall-clock limit
+        if self.config.stopwalltime>0.0 && starttime.elapsed().as_secs_f64()>=self.config.stopwalltime {
+            return Some(String::from("walltime"));
+        }
+
+        None
+    }
+
+    fn prepare_pop(&self,pop: &mut Population) -> Result<(),Box<dyn Error>>{
+        if self.config.mode=="sswm" {
+            let (domx,domy,_) = pop.get_dominance_info();
+            pop.set_monomorphic(domx as u64,domy as u64,self.config.popsize); // Start from the dominant genotype of the initial population
+        }
+        if self.config.mode=="invasion" {
+            // Replace invadek random residents with mutants at invadexy
+            let (mutx,muty) = (self.config.invadexy[0],self.config.invadexy[1]);
+            let mutpid = self.gpmap.get_pid_at(mutx,muty);
+            if pop.get_pheno_counts(&self.gpmap).iter().any(|(pid,c)| *pid==mutpid && *c>0.0) {
+                return Err(format!("the resident population already has the mutant phenotype {}",mutpid).into());
+            }
+            pop.introduce_mutants(mutx,muty,self.config.invadek);
+        }
+        Ok(())
+    }
+
+    pub fn select_mutate(&self,pop: &mut Population,gfmap: &[Vec<f64>],mut lineage: Option<&mut Lineage>,time: u64) -> bool{
+        let mut gtypes_in_pop:Vec<(u64,u64,usize,u64)> = Vec::new(); // (x,y,mutation rate level,lineage node) of each parental class
+        let mut weights: Vec<f64> = Vec::new();
+
+
+        if let Some(lineage) = lineage.as_ref(){
+            for (id,x,y,level,count) in lineage.get_living(){
+                gtypes_in_pop.push((x,y,level,id));
+                weights.push(gfmap[x as usize][y as usize]*(count as f64));
+            }
+        } else {
+            for (x,y,level,count) in pop.get_classes(){
+                gtypes_in_pop.push((x,y,level,0));
+                weights.push(gfmap[x as usize][y as usize]*count);
+            }
         }
 
+        if weights.iter().sum::<f64>()<=0.0 {
+            return false; // Nobody can reproduce
+        }
         let dist = WeightedIndex::new(&weights).unwrap();
         let mut rng = thread_rng();
 
         let sampled_indices: Vec<usize> = dist.sample_iter(&mut rng).take(self.config.popsize as usize).collect();
+
+        // Number of mutations per offspring at each mutation rate level (None if the level never mutates)
+        let kdists: Vec<Option<Poisson>> = pop.get_mutlevels().iter().map(|m| {
+            let lamb = (1.00/(1.00-m)).ln();
+            if lamb>0.0 { Some(Poisson::new(lamb).unwrap()) } else { None } // Pick from a geometric dist with p(k) = m^k (1-m)^k
+        }).collect();
+        let nlevels = kdists.len();
         
-        let mut new_pop: Vec<Vec<u64>> = vec![vec![0; self.config.grid_y as usize];self.config.grid_x as usize];
+        let mut new_pop: Vec<Vec<f64>> = vec![vec![0.0; self.config.grid_y as usize];self.config.grid_x as usize];
+        let mut new_modpop: HashMap<(u64,u64,usize),u64> = HashMap::new(); // Offspring per (x,y,level) class
+        let mut new_counts: HashMap<u64,u64> = HashMap::new(); // Offspring per lineage node
 
         for smp_index in sampled_indices.iter(){
-            let (mut x, mut y) = gtypes_in_pop[*smp_index];
-
-
-            let lamb = (1.00/(1.00-self.config.mutprob)).ln();
-            let k = Poisson::new(lamb).unwrap(); // Pick from a geometric dist with p(k) = m^k (1-m)^k
+            let (mut x, mut y, mut level, parent) = gtypes_in_pop[*smp_index];
 
 
-            for _ in 0..k.sample(&mut rand::thread_rng()) as usize{
+            let nmutations = kdists[level].as_ref().map_or(0,|kdist| kdist.sample(&mut rng) as usize);
+            for _ in 0..nmutations{
                 self.mutate_xy(&mut x,&mut y);
             }
 
+            // Mutate the modifier to a neighbouring level (reflecting at the bounds)
+            if nlevels>1 && rng.gen::<f64>()<self.config.mutmodprob {
+                level = if level==0 {
+                    1
+                } else if level==nlevels-1 || rng.gen::<bool>() {
+                    level-1
+                } else {
+                    level+1
+                };
+            }
+
 
-            new_pop[x as usize][y as usize] += 1;
+            new_pop[x as usize][y as usize] += 1.0;
+            if pop.evolving_mutrates(){
+                *new_modpop.entry((x,y,level)).or_insert(0) += 1;
+            }
+
+            if let Some(lineage) = lineage.as_mut(){
+                let child = lineage.add_offspring(parent,x,y,level,time);
+                *new_counts.entry(child).or_insert(0) += 1;
+            }
         }
 
         pop.set_pop(new_pop);
+        pop.set_modpop(new_modpop);
+
+        if let Some(lineage) = lineage{
+            lineage.set_counts(&new_counts);
+        }
+        true
     }
 
-    pub fn mutate_xy(&self,x: &mut u64, y: &mut u64){
-        let dir: i32 = *vec![0,1,2,3].choose(&mut rand::thread_rng()).unwrap() as i32;
-    
-        
-        match dir{
-            0 => { // North
-                if *y == self.config.grid_y-1{
-                    *y = 0;
-                } else {
-                    *y +=1;
-                }
-            }
-            1 => { // East
-                if *x == self.config.grid_x-1{
-                    *x = 0;
-                } else {
-                    *x +=1;
-                }
-            }
-            2 => { // South
-                if *y == 0{
-                    *y = self.config.grid_y-1;
-                } else {
-                    *y -=1;
-                }
+    pub fn select_mutate_meanfield(&self,pop: &mut Population,gfmap: &[Vec<f64>]) -> bool{
+        // Selection: abundances grow in proportion to fitness and are rescaled to popsize
+        let mut selected: Vec<Vec<f64>> = vec![vec![0.0; self.config.grid_y as usize];self.config.grid_x as usize];
+        let mut total: f64 = 0.0;
+
+        for (idx,row) in gfmap.iter().enumerate(){
+            for (idy,fitness) in row.iter().enumerate(){
+                selected[idx][idy] = fitness*pop.get_at(idx as u64,idy as u64);
+                total += selected[idx][idy];
             }
-            3 => { // West
-                if *x == 0{
-                    *x = self.config.grid_x-1;
-                } else {
-                    *x -=1;
+        }
+
+        if total<=0.0 {
+            return false; // Nobody can reproduce
+        }
+        selected.iter_mut().for_each(|a| a.iter_mut().for_each(|b| *b *= self.config.popsize as f64/total));
+
+        // Mutation: each offspring takes k ~ Poisson(lamb) steps of the mutational random walk
+        let lamb = (1.00/(1.00-self.config.mutprob)).ln();
+        if lamb==0.0 {
+            pop.set_pop(selected); // Without mutation the selected abundances are the next generation
+            return true;
+        }
+        let kdist = Poisson::new(lamb).unwrap();
+
+        let mut steps = selected; // Abundances after k mutational steps
+        let mut new_pop: Vec<Vec<f64>> = vec![vec![0.0; self.config.grid_y as usize];self.config.grid_x as usize];
+        let mut cumprob: f64 = 0.0;
+        let mut k: u64 = 0;
+
+        loop {
+            let prob = kdist.pmf(k);
+            cumprob += prob;
+
+            // Stop once the remaining Poisson tail is negligible and lump it into the last step
+            let last = 1.0-cumprob<1e-12 || k>=1000;
+            let weight = if last { prob+(1.0-cumprob).max(0.0) } else { prob };
+
+            for (idx,row) in steps.iter().enumerate(){
+                for (idy,size) in row.iter().enumerate(){
+                    new_pop[idx][idy] += size*weight;
                 }
             }
-            _ => {
+            if last {
+                break;
             }
+
+            steps = self.gpmap.spread_mutations(&steps);
+            k += 1;
+        }
+
+        pop.set_pop(new_pop);
+        true
+    }
+
+    pub fn select_mutate_sswm(&self,pop: &mut Population,gfmap: &[Vec<f64>],wait: &mut Option<f64>) -> bool{
+        let (x,y,_) = pop.get_dominance_info();
+        let resfitness = gfmap[x][y];
+        if resfitness<=0.0 {
+            return false; // Nobody can reproduce
+        }
+
+        // Rate (per generation) at which mutants in each direction arise and go on to fix
+        let weights = self.gpmap.get_dirweights_at(x as u64,y as u64);
+        let wsum: f64 = weights.iter().sum();
+        let mut rates: Vec<f64> = Vec::new();
+        for (dir,weight) in weights.iter().enumerate(){
+            let (nx,ny) = self.gpmap.get_neighbour(x as u64,y as u64,dir);
+            let mutfitness = gfmap[nx as usize][ny as usize];
+            let fixprob = get_fixprob(mutfitness/resfitness-1.0,self.config.popsize);
+            rates.push(self.config.popsize as f64*self.config.mutprob*weight/wsum*fixprob);
+        }
+        let totalrate: f64 = rates.iter().sum();
+
+        // Waiting time to the next fixation is exponentially distributed
+        let mut rng = thread_rng();
+        let remaining = wait.get_or_insert_with(|| {
+            if totalrate>0.0 { -(1.0-rng.gen::<f64>()).ln()/totalrate } else { f64::INFINITY }
+        });
+        *remaining -= 1.0;
+
+        if *remaining<=0.0 {
+            let dir = WeightedIndex::new(&rates).unwrap().sample(&mut rng);
+            let (nx,ny) = self.gpmap.get_neighbour(x as u64,y as u64,dir);
+            pop.set_monomorphic(nx,ny,self.config.popsize);
+            *wait = None;
         }
+        true
+    }
+
+    pub fn mutate_xy(&self,x: &mut u64, y: &mut u64){
+        let dir = self.gpmap.sample_direction(*x,*y);
+        (*x,*y) = self.gpmap.get_neighbour(*x,*y,dir);
     }
 }
 
 
+pub struct SimulationBuilder{
+    config: Config,
+    gpmap: Vec<Vec<u64>>,
+    env: Environment,
+    pop: Vec<Vec<f64>>,
+    observers: Vec<Box<dyn Observer>>,
+    verbose: bool, // Print the progress of runs?
+    record: bool, // Keep the results of every output generation in memory?
+}
+
+
+impl SimulationBuilder{
+    pub fn new(gpmap: Vec<Vec<u64>>, env: Environment, pop: Vec<Vec<f64>>) -> SimulationBuilder{
+        // Map (phenotype ids) and population (abundances) are indexed by x and y, no files are read or written
+        SimulationBuilder { config: Config::default(), gpmap, env, pop, observers: Vec::new(), verbose: false, record: false }
+    }
+
+    pub fn config(mut self, config: Config) -> SimulationBuilder{
+        // Parameters of the run (grid size and popsize are taken from the map and the population, file options are ignored)
+        self.config = config;
+        self
+    }
+
+    pub fn mutprob(mut self, mutprob: f64) -> SimulationBuilder{
+        self.config.mutprob = mutprob;
+        self
+    }
+
+    pub fn mode(mut self, mode: &str) -> SimulationBuilder{
+        self.config.mode = String::from(mode);
+        self
+    }
+
+    pub fn maxgens(mut self, maxgens: u64) -> SimulationBuilder{
+        self.config.maxgens = maxgens;
+        self
+    }
+
+    pub fn saveevery(mut self, saveevery: u64) -> SimulationBuilder{
+        self.config.saveevery = saveevery;
+        self
+    }
+
+    pub fn observer(mut self, observer: Box<dyn Observer>) -> SimulationBuilder{
+        self.observers.push(observer);
+        self
+    }
+
+    pub fn verbose(mut self, verbose: bool) -> SimulationBuilder{
+        self.verbose = verbose;
+        self
+    }
+
+    pub fn record(mut self, record: bool) -> SimulationBuilder{
+        self.record = record;
+        self
+    }
+
+    pub fn build(self) -> Result<Simulation,Box<dyn Error>>{
+        let mut config = self.config;
+        if self.gpmap.is_empty() || self.gpmap[0].is_empty() {
+            return Err("the genotype-phenotype map should not be empty".into());
+        }
+        config.grid_x = self.gpmap.len() as u64;
+        config.grid_y = self.gpmap[0].len() as u64;
+        config.popsize = self.pop.iter().flatten().sum::<f64>().round() as u64;
+        if config.popsize==0 {
+            return Err("the population should not be empty".into());
+        }
+        config.validate()?;
+
+        let gpmap = Gpmap::from_grid(&config,self.gpmap)?;
+        check_pfmaps(&self.env,&gpmap)?;
+        let pop = Population::from_grid(&config,self.pop)?;
+
+        let records = if self.record { Some(Vec::new()) } else { None };
+        Ok(Simulation { config, output: Output::default(), env: self.env, gpmap, initpop: pop, observers: self.observers, state: None, regenerate: false, verbose: self.verbose, records })
+    }
+}
+
+
+fn check_pfmaps(env: &Environment, gpmap: &Gpmap) -> Result<(),Box<dyn Error>>{
+    // Every environment needs a fitness for every phenotype id of the map (built-in maps list theirs in phenotypes.csv)
+    let maxpid = *gpmap.get_pid_list().iter().max().unwrap();
+    if env.get_pfmaps().iter().any(|pfmap| pfmap.len()<=maxpid as usize) {
+        return Err(format!("every phenotype fitness map should give the fitness of phenotypes 0 to {}",maxpid).into());
+    }
+    Ok(())
+}
+
+
+pub fn get_fixprob(s: f64, popsize: u64) -> f64 {
+    // Kimura's fixation probability of a single mutant with selection coefficient s in a population of size popsize
+    if s<=-1.0 {
+        return 0.0;
+    }
+    if s.abs()<1e-12 {
+        return 1.0/popsize as f64;
+    }
+    let fixprob = (-2.0*s).exp_m1()/(-2.0*s*popsize as f64).exp_m1(); // exp_m1 keeps weak selection accurate
+    if fixprob.is_finite() { fixprob } else { 0.0 }
+}
+
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn fixprob_limits(){
+        // Neutral mutants fix with probability 1/N, lethal ones never
+        assert_eq!(get_fixprob(0.0,100),0.01);
+        for s in [1e-11,-1e-11,1e-9,-1e-9]{
+            // First order in s: 1/N+s(N-1)/N
+            assert!((get_fixprob(s,100)-(0.01+s*0.99)).abs()<1e-15);
+        }
+        assert_eq!(get_fixprob(-1.0,100),0.0);
+        assert_eq!(get_fixprob(-5.0,100),0.0);
+    }
+
+    #[test]
+    fn fixprob_strong_selection(){
+        // Beneficial mutants fix with probability 1-exp(-2s) in large populations, deleterious ones hardly ever
+        assert!((get_fixprob(0.1,10000)-(1.0-(-0.2_f64).exp())).abs()<1e-12);
+        assert!(get_fixprob(-0.1,10000)<1e-300);
+        assert!(get_fixprob(-0.5,1_000_000).is_finite());
+    }
+}
@@ -1,16 +1,45 @@
-use std::{error::Error, fs,cmp};
+use std::{error::Error, fs};
 use crate::config::Config;
+use rand::Rng;
 use rand::seq::SliceRandom;
-use statrs::distribution::{Poisson,Discrete};
+use crate::rna;
+use crate::hp;
+use crate::grn;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Write;
+
+#[derive(Clone,Copy,PartialEq)]
+pub enum Space{
+    Grid,                                                   // 2D grid with periodic boundaries, mutations step north, east, south or west
+    Sequence{ len: usize, nletters: usize, xsites: usize }, // Sequences of len letters (the first xsites sites give x and the rest y), mutations substitute a single letter
+}
 
 pub struct Gpmap{
     gpmap: Vec<Vec<u64>>, // For a grid implementation the pid is obtained from a 2d vector where first index is x and second index is y
-    pid_list: Vec<u64>
+    pid_list: Vec<u64>,
+    dirweights: Vec<Vec<[f64;4]>>, // Relative weights of mutating (north,east,south,west) from each genotype (empty for sequence spaces)
+    space: Space,
+    pheno_names: Vec<String>, // Name of each phenotype of a built-in map, indexed by pid (empty for maps read from a file)
 }
 
 
 impl Gpmap{
     pub fn parse_gpmap(cfg: &Config) -> Result<Gpmap,Box<dyn Error>>{
+        match cfg.gpmtype.as_str(){
+            "file" => {},
+            "rna" => return Ok(Gpmap::from_sequences(cfg,Some(&".".repeat(cfg.seqlen as usize)),rna::fold)),
+            "hp" => {
+                let folds = hp::get_folds(cfg)?;
+                return Ok(Gpmap::from_sequences(cfg,Some("unfolded"),|seq| hp::fold(seq,&folds)));
+            },
+            "grn" => {
+                let init = grn::get_initial_state(&cfg.grninit,cfg.ngenes as usize);
+                return Ok(Gpmap::from_sequences(cfg,None,|seq| grn::get_attractor(seq,&cfg.grnweights,&init)));
+            },
+            _ => return Err(format!("unknown gp-map type {}",cfg.gpmtype).into()),
+        }
+
         let mut gpmvec:Vec<Vec<u64>> = vec![vec![0; cfg.grid_y as usize]; cfg.grid_x as usize]; // Create an empty gpmap
         let mut pid_list: Vec<u64> = Vec::new();
 
@@ -20,19 +49,114 @@ impl Gpmap{
             let row_as_vec: Vec<&str> = row.split(",").collect();
             for(idx,cellval) in row_as_vec.iter().enumerate(){
                 let pid = cellval.parse::<u64>().unwrap();
-                gpmvec[idx as usize][idy as usize]= pid;
+                gpmvec[idx][idy]= pid;
                 if !pid_list.contains(&pid){
                     pid_list.push(pid);
                 }
             }
         }
 
-        let gpmap: Gpmap = Gpmap { gpmap: gpmvec, pid_list: pid_list };
+        let dirweights = Gpmap::parse_dirweights(cfg)?;
+
+        let gpmap: Gpmap = Gpmap { gpmap: gpmvec, pid_list, dirweights, space: Space::Grid, pheno_names: Vec::new() };
         Ok(gpmap)
     }
 
+    fn from_sequences(cfg: &Config, unfolded: Option<&str>, get_phenotype: impl Fn(&[usize]) -> String) -> Gpmap{
+        // Every sequence gets the phenotype computed from it, with ids assigned in order of appearance (pid 0 is kept for the unfolded phenotype)
+        let space = Space::Sequence { len: cfg.seqlen as usize, nletters: cfg.get_nletters() as usize, xsites: cfg.seqlen.div_ceil(2) as usize };
+        let mut gpmvec: Vec<Vec<u64>> = vec![vec![0; cfg.grid_y as usize]; cfg.grid_x as usize];
+        let mut pid_list: Vec<u64> = Vec::new();
+        let mut pheno_names: Vec<String> = unfolded.iter().map(|name| name.to_string()).collect();
+        let mut pids: HashMap<String,u64> = pheno_names.iter().map(|name| (name.clone(),0)).collect();
+        let mut seen: Vec<bool> = Vec::new();
 
-    pub fn get_random_xy(&self,vec_pids: &Vec<u64>) -> (u64,u64){
+        for idy in 0..cfg.grid_y{
+            for idx in 0..cfg.grid_x{
+                let name = get_phenotype(&space.get_sequence(idx,idy));
+                let pid = *pids.entry(name).or_insert_with_key(|name| {
+                    pheno_names.push(name.clone());
+                    (pheno_names.len()-1) as u64
+                });
+                gpmvec[idx as usize][idy as usize] = pid;
+                if seen.len()<=pid as usize {
+                    seen.resize(pid as usize+1,false);
+                }
+                if !seen[pid as usize] {
+                    seen[pid as usize] = true;
+                    pid_list.push(pid);
+                }
+            }
+        }
+
+        Gpmap { gpmap: gpmvec, pid_list, dirweights: Vec::new(), space, pheno_names }
+    }
+
+    pub fn from_grid(cfg: &Config, gpmvec: Vec<Vec<u64>>) -> Result<Gpmap,Box<dyn Error>>{
+        // Map given directly as phenotype ids indexed by x and y (mutation weights from mutweights, as without a bias file)
+        if gpmvec.len()!=cfg.grid_x as usize || gpmvec.iter().any(|row| row.len()!=cfg.grid_y as usize) {
+            return Err(format!("the genotype-phenotype map should be a {} by {} grid",cfg.grid_x,cfg.grid_y).into());
+        }
+
+        let mut pid_list: Vec<u64> = Vec::new();
+        for idy in 0..cfg.grid_y as usize{
+            for row in gpmvec.iter(){
+                if !pid_list.contains(&row[idy]){
+                    pid_list.push(row[idy]);
+                }
+            }
+        }
+
+        let global: [f64;4] = [cfg.mutweights[0],cfg.mutweights[1],cfg.mutweights[2],cfg.mutweights[3]];
+        let dirweights: Vec<Vec<[f64;4]>> = vec![vec![global; cfg.grid_y as usize]; cfg.grid_x as usize];
+
+        Ok(Gpmap { gpmap: gpmvec, pid_list, dirweights, space: Space::Grid, pheno_names: Vec::new() })
+    }
+
+    fn parse_dirweights(cfg: &Config) -> Result<Vec<Vec<[f64;4]>>,Box<dyn Error>>{
+        // Every genotype uses the global direction weights unless overridden in the bias file
+        let global: [f64;4] = [cfg.mutweights[0],cfg.mutweights[1],cfg.mutweights[2],cfg.mutweights[3]];
+        let mut dirweights: Vec<Vec<[f64;4]>> = vec![vec![global; cfg.grid_y as usize]; cfg.grid_x as usize];
+
+        if cfg.biasfilepath.as_os_str().is_empty(){
+            return Ok(dirweights);
+        }
+
+        // Bias file rows are x,y,north,east,south,west
+        let biascontent = fs::read_to_string(&cfg.biasfilepath)?;
+        for (lineno,row) in biascontent.lines().enumerate(){
+            let row_as_vec: Vec<&str> = row.split(",").collect();
+            if row_as_vec.len()!=6 {
+                return Err(format!("line {} of the bias file should be x,y,north,east,south,west", lineno+1).into());
+            }
+            let x = row_as_vec[0].trim().parse::<u64>()?;
+            let y = row_as_vec[1].trim().parse::<u64>()?;
+            if x>=cfg.grid_x || y>=cfg.grid_y {
+                return Err(format!("line {} of the bias file is outside the grid", lineno+1).into());
+            }
+
+            let mut weights: [f64;4] = [0.0;4];
+            for (dir,weight) in weights.iter_mut().enumerate(){
+                *weight = row_as_vec[dir+2].trim().parse::<f64>()?;
+            }
+            if weights.iter().any(|w| *w<0.0) || weights.iter().sum::<f64>()<=0.0 {
+                return Err(format!("line {} of the bias file should have non-negative weights with a positive sum", lineno+1).into());
+            }
+            dirweights[x as usize][y as usize] = weights;
+        }
+
+        Ok(dirweights)
+    }
+
+
+    pub fn get_random_xy(&self,vec_pids: &[u64]) -> (u64,u64){
+        let all_matching_xys = self.get_matching_xys(vec_pids);
+
+        let chosen_xy = all_matching_xys.choose(&mut rand::thread_rng()).unwrap();
+        *chosen_xy
+    }
+
+    pub fn get_matching_xys(&self,vec_pids: &[u64]) -> Vec<(u64,u64)>{
         let mut all_matching_xys: Vec<(u64,u64)> = Vec::new();
 
         for (idx,row) in self.gpmap.iter().enumerate(){
@@ -43,12 +167,11 @@ impl Gpmap{
             }
         }
 
-        let chosen_xy = all_matching_xys.choose(&mut rand::thread_rng()).unwrap();
-        *chosen_xy
+        all_matching_xys
     }
 
 
-    pub fn get_gfmap(&self, pfmap: &Vec<f64>) -> Vec<Vec<f64>>{
+    pub fn get_gfmap(&self, pfmap: &[f64]) -> Vec<Vec<f64>>{
         let mut gfmap: Vec<Vec<f64>> = vec![vec![0.0; self.gpmap[0].len()]; self.gpmap.len()];
 
         for (idx,row) in self.gpmap.iter().enumerate(){
@@ -60,77 +183,303 @@ impl Gpmap{
         gfmap
     }
 
-    pub fn get_evol_at(&self, cfg:&Config, x: u64, y: u64) -> f64 {
-        // get self pid
+    pub fn get_evol_at(&self, x: u64, y: u64) -> f64 {
+        // Fraction of neighbours (one point mutation away) that have a different pid
         let self_pid = self.gpmap[x as usize][y as usize];
-        // get number of neighbors that have different pid
-        let mut num_diff:f64  = 0.0;
+        let nneighbours = self.get_nneighbours();
 
-        let north_pid:u64;
-        let east_pid:u64;
-        let south_pid:u64;
-        let west_pid:u64;
+        let num_diff = (0..nneighbours).filter(|dir| {
+            let (nx,ny) = self.get_neighbour(x,y,*dir);
+            self.gpmap[nx as usize][ny as usize]!=self_pid
+        }).count();
+
+        num_diff as f64/nneighbours as f64
+    }
 
-        // Check north neighbor
-        if y==cfg.grid_y-1{
-            north_pid = self.gpmap[x as usize][0];
-        } else {
-            north_pid = self.gpmap[x as usize][(y+1) as usize];
+    pub fn get_mutprob_table(&self, mutprob_self: f64) -> Vec<Vec<Vec<f64>>> {
+        // Probability of an offspring of each genotype having each phenotype after k ~ Poisson(lamb) mutational steps,
+        // indexed by x, y and position in the phenotype list
+        if let Space::Sequence{ .. } = self.space {
+            return self.get_sequence_mutprob_table(mutprob_self);
         }
+        let pidpos = self.get_pid_positions();
+        let npheno = self.pid_list.len();
+
+        // Weighted steps from every genotype to its neighbours
+        let steps: Vec<Vec<Vec<(usize,usize,f64)>>> = (0..self.gpmap.len()).map(|idx| (0..self.gpmap[0].len()).map(|idy| {
+            let weights = self.get_dirweights_at(idx as u64,idy as u64);
+            let wsum: f64 = weights.iter().sum();
+            weights.iter().enumerate().filter(|(_,weight)| **weight>0.0).map(|(dir,weight)| {
+                let (nx,ny) = self.get_neighbour(idx as u64,idy as u64,dir);
+                (nx as usize,ny as usize,weight/wsum)
+            }).collect()
+        }).collect()).collect();
+
+        // Probability that a walk of k steps from each genotype ends on each phenotype (backward equation of the walk)
+        let mut reach: Vec<Vec<Vec<f64>>> = self.gpmap.iter().map(|row| row.iter().map(|pid| {
+            let mut onpheno = vec![0.0; npheno];
+            onpheno[pidpos[*pid as usize]] = 1.0;
+            onpheno
+        }).collect()).collect();
+        let mut table: Vec<Vec<Vec<f64>>> = vec![vec![vec![0.0; npheno]; self.gpmap[0].len()]; self.gpmap.len()];
+
+        let lamb = (1.00/(1.00-mutprob_self)).ln();
+        let mut prob = (-lamb).exp(); // Probability of k steps
+        let mut k: u64 = 0;
+        loop{
+            for (tablerow,reachrow) in table.iter_mut().zip(reach.iter()){
+                for (probs,onpheno) in tablerow.iter_mut().zip(reachrow.iter()){
+                    probs.iter_mut().zip(onpheno.iter()).for_each(|(p,r)| *p += prob*r);
+                }
+            }
 
-        // Check east neighbor
-        if x==cfg.grid_x-1{
-            east_pid = self.gpmap[0][y as usize];
-        } else {
-            east_pid = self.gpmap[(x+1) as usize][y as usize];
+            // Stop once the probability of more steps is below the smallest double
+            k += 1;
+            prob *= lamb/k as f64;
+            if prob==0.0 || k>1000 {
+                break;
+            }
+            reach = steps.iter().map(|row| row.iter().map(|cellsteps| {
+                let mut onpheno = vec![0.0; npheno];
+                for (nx,ny,weight) in cellsteps.iter(){
+                    onpheno.iter_mut().zip(reach[*nx][*ny].iter()).for_each(|(p,r)| *p += weight*r);
+                }
+                onpheno
+            }).collect()).collect();
         }
+        table
+    }
+
+    fn get_sequence_mutprob_table(&self, mutprob_self: f64) -> Vec<Vec<Vec<f64>>> {
+        // Sequence version: sites change independently, so the phenotype indicators are mixed one site at a time
+        let Space::Sequence{ len, nletters, xsites } = self.space else { unreachable!() };
+        let (keep,change) = get_site_probs(mutprob_self,len,nletters);
+        let pidpos = self.get_pid_positions();
+        let npheno = self.pid_list.len();
+        let (grid_x,grid_y) = (self.gpmap.len(),self.gpmap[0].len());
+
+        let mut table: Vec<Vec<Vec<f64>>> = self.gpmap.iter().map(|row| row.iter().map(|pid| {
+            let mut onpheno = vec![0.0; npheno];
+            onpheno[pidpos[*pid as usize]] = 1.0;
+            onpheno
+        }).collect()).collect();
 
+        // Each genotype keeps keep times its own value and gets change times the values of the genotypes with another letter at the site
+        let mix = |cells: &[(usize,usize)], table: &mut Vec<Vec<Vec<f64>>>| {
+            let mut totals = vec![0.0; npheno];
+            for (x,y) in cells.iter(){
+                totals.iter_mut().zip(table[*x][*y].iter()).for_each(|(total,p)| *total += p);
+            }
+            for (x,y) in cells.iter(){
+                table[*x][*y].iter_mut().zip(totals.iter()).for_each(|(p,total)| *p = (keep-change)**p+change*total);
+            }
+        };
+        for site in 0..len{
+            if site<xsites {
+                let place = nletters.pow((xsites-1-site) as u32);
+                for idx in (0..grid_x).filter(|idx| idx/place%nletters==0){
+                    for idy in 0..grid_y{
+                        let cells: Vec<(usize,usize)> = (0..nletters).map(|letter| (idx+letter*place,idy)).collect();
+                        mix(&cells,&mut table);
+                    }
+                }
+            } else {
+                let place = nletters.pow((len-1-site) as u32);
+                for idy in (0..grid_y).filter(|idy| idy/place%nletters==0){
+                    for idx in 0..grid_x{
+                        let cells: Vec<(usize,usize)> = (0..nletters).map(|letter| (idx,idy+letter*place)).collect();
+                        mix(&cells,&mut table);
+                    }
+                }
+            }
+        }
+        table
+    }
 
-        // Check south neighbor 
-        if y==0{
-            south_pid = self.gpmap[x as usize][(cfg.grid_y-1) as usize];
-        } else {
-            south_pid = self.gpmap[x as usize][(y-1) as usize];
+    pub fn get_pid_positions(&self) -> Vec<usize> {
+        // Position of every pid in the phenotype list (indexed by pid)
+        let maxpid = *self.pid_list.iter().max().unwrap() as usize;
+        let mut pidpos: Vec<usize> = vec![0; maxpid+1];
+        for (pos,pid) in self.pid_list.iter().enumerate(){
+            pidpos[*pid as usize] = pos;
         }
+        pidpos
+    }
 
-        // Check west neighbor
-        if x==0{
-            west_pid = self.gpmap[(cfg.grid_x-1) as usize][y as usize];
-        } else {
-            west_pid = self.gpmap[(x-1) as usize][y as usize];
+    pub fn spread_mutations(&self, abund: &[Vec<f64>]) -> Vec<Vec<f64>> {
+        // Move every genotype's abundance one mutational step, split among directions by the weights at its source
+        let grid_x = self.gpmap.len();
+        let grid_y = self.gpmap[0].len();
+        let mut spread: Vec<Vec<f64>> = vec![vec![0.0; grid_y]; grid_x];
+
+        for (idx,row) in abund.iter().enumerate(){
+            for (idy,size) in row.iter().enumerate(){
+                if *size==0.0 {
+                    continue;
+                }
+                let weights = self.get_dirweights_at(idx as u64,idy as u64);
+                let wsum: f64 = weights.iter().sum();
+
+                for (dir,weight) in weights.iter().enumerate(){
+                    let (nx,ny) = self.get_neighbour(idx as u64,idy as u64,dir);
+                    spread[nx as usize][ny as usize] += size*weight/wsum;
+                }
+            }
         }
 
-        if north_pid!=self_pid {num_diff += 1.0;}
-        if east_pid!=self_pid {num_diff += 1.0;}
-        if south_pid!=self_pid {num_diff += 1.0;}
-        if west_pid!=self_pid {num_diff += 1.0;}
+        spread
+    }
 
-        num_diff/4.0
+    pub fn get_nneighbours(&self) -> usize {
+        // Number of genotypes one point mutation away (mutation directions)
+        match self.space{
+            Space::Grid => 4,
+            Space::Sequence{ len, nletters, .. } => len*(nletters-1),
+        }
     }
 
-    pub fn get_mutprob(&self, cfg:&Config, target_pid: u64, x_self: u64, y_self: u64) -> f64 {
-        let mut mutprob: f64 = 0.0;
+    pub fn get_neighbour(&self, x: u64, y: u64, dir: usize) -> (u64,u64) {
+        // Genotype one mutational step away in the given direction (periodic boundaries on a grid, letter dir%(nletters-1)+1 places further at site dir/(nletters-1) of a sequence)
+        let grid_x = self.gpmap.len() as u64;
+        let grid_y = self.gpmap[0].len() as u64;
 
-        for (idx,row) in self.gpmap.iter().enumerate(){
-            for (idy, pid) in row.iter().enumerate(){
-                if *pid==target_pid{
-                    let del_x = cmp::min((x_self as i32-idx as i32).abs(),idx as i32+(cfg.grid_x as i32-x_self as i32));
-                    let del_y = cmp::min((y_self as i32-idy as i32).abs(),idy as i32+(cfg.grid_y as i32-y_self as i32));
+        match self.space{
+            Space::Grid => match dir{
+                0 => (x,(y+1)%grid_y),        // North
+                1 => ((x+1)%grid_x,y),        // East
+                2 => (x,(y+grid_y-1)%grid_y), // South
+                3 => ((x+grid_x-1)%grid_x,y), // West
+                _ => (x,y),
+            },
+            Space::Sequence{ len, nletters, xsites } => {
+                let (site,shift) = (dir/(nletters-1),(dir%(nletters-1)+1) as u64);
+                let nletters = nletters as u64;
+                if site<xsites {
+                    let place = nletters.pow((xsites-1-site) as u32);
+                    let letter = x/place%nletters;
+                    (x-letter*place+(letter+shift)%nletters*place,y)
+                } else {
+                    let place = nletters.pow((len-1-site) as u32);
+                    let letter = y/place%nletters;
+                    (x,y-letter*place+(letter+shift)%nletters*place)
+                }
+            },
+        }
+    }
+
+    pub fn get_reverse_dir(&self, dir: usize) -> usize {
+        // Direction of the mutation leading back from the neighbour in direction dir
+        match self.space{
+            Space::Grid => (dir+2)%4,
+            Space::Sequence{ nletters, .. } => {
+                let (site,shift) = (dir/(nletters-1),dir%(nletters-1)+1);
+                site*(nletters-1)+nletters-shift-1
+            },
+        }
+    }
 
-                    let k = del_x+del_y;
+    pub fn get_dirweights_at(&self, x: u64, y: u64) -> Vec<f64> {
+        match self.space{
+            Space::Grid => self.dirweights[x as usize][y as usize].to_vec(),
+            Space::Sequence{ .. } => vec![1.0; self.get_nneighbours()], // Substitutions are equally likely
+        }
+    }
 
-                    let lamb = (1.00/(1.00-cfg.mutprob)).ln();
-                    let dist = Poisson::new(lamb).unwrap();
-                    let prob = dist.pmf(k as u64);
+    pub fn get_dirweight_at(&self, x: u64, y: u64, dir: usize) -> f64 {
+        match self.space{
+            Space::Grid => self.dirweights[x as usize][y as usize][dir],
+            Space::Sequence{ .. } => 1.0,
+        }
+    }
 
-                    mutprob += prob;
-                }
+    pub fn sample_direction(&self, x: u64, y: u64) -> usize {
+        // Pick a mutation direction (0 north, 1 east, 2 south, 3 west on a grid) using the weights at x,y
+        if let Space::Sequence{ .. } = self.space {
+            return rand::thread_rng().gen_range(0..self.get_nneighbours());
+        }
+        let weights = self.dirweights[x as usize][y as usize];
+        let mut r = rand::thread_rng().gen::<f64>()*weights.iter().sum::<f64>();
+        for (dir,weight) in weights.iter().enumerate(){
+            if r<*weight {
+                return dir;
             }
+            r -= weight;
         }
-        mutprob
+        weights.iter().rposition(|w| *w>0.0).unwrap()
+    }
+
+    pub fn get_pid_at(&self, x: u64, y: u64) -> u64{
+        self.gpmap[x as usize][y as usize]
     }
 
     pub fn get_pid_list(&self) -> &Vec<u64>{
         &self.pid_list
     }
-}
\ No newline at end of file
+
+    pub fn get_space(&self) -> Space{
+        self.space
+    }
+
+    pub fn get_pheno_names(&self) -> &[String]{
+        &self.pheno_names
+    }
+
+    pub fn write_phenotypes(&self, dir: &str) -> Result<(),Box<dyn Error>>{
+        // Phenotype of every pid of a built-in map
+        let mut phenofile = File::create(format!("{}/phenotypes.csv",dir))?;
+        phenofile.write_all(b"pid,phenotype\n")?;
+        for (pid,name) in self.pheno_names.iter().enumerate(){
+            phenofile.write_all(format!("{},{}\n",pid,name).as_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+
+impl Space{
+    pub fn get_sequence(&self, x: u64, y: u64) -> Vec<usize> {
+        // Letters of the sequence at x,y (the first site is the most significant digit of x)
+        match *self{
+            Space::Grid => Vec::new(),
+            Space::Sequence{ len, nletters, xsites } => {
+                let mut seq = get_digits(x as usize,xsites,nletters);
+                seq.extend(get_digits(y as usize,len-xsites,nletters));
+                seq
+            },
+        }
+    }
+}
+
+
+fn get_digits(value: usize, ndigits: usize, base: usize) -> Vec<usize> {
+    // Base-base digits of value, most significant first
+    let mut digits: Vec<usize> = vec![0; ndigits];
+    let mut rest = value;
+    for digit in digits.iter_mut().rev(){
+        *digit = rest%base;
+        rest /= base;
+    }
+    digits
+}
+
+
+fn get_site_probs(mutprob_self: f64, len: usize, nletters: usize) -> (f64,f64) {
+    // Probability that a site ends with its own letter and with each other letter, when the k ~ Poisson(lamb) substitutions fall on uniformly chosen sites and letters
+    let lamb = (1.00/(1.00-mutprob_self)).ln();
+    let a = nletters as f64;
+    let keep = 1.0/a+(a-1.0)/a*(-lamb/len as f64*a/(a-1.0)).exp();
+    (keep,(1.0-keep)/(a-1.0))
+}
+
+
+pub fn get_torus_dist(from: u64, to: u64, size: u64) -> u64 {
+    // Number of steps between two coordinates along a periodic axis
+    let forward = (to%size+size-from%size)%size;
+    forward.min(size-forward)
+}
+
+
+pub fn get_wrapped_delta(from: f64, to: f64, size: f64) -> f64 {
+    // Signed shortest displacement between two (possibly fractional) positions along a periodic axis
+    (to-from+size/2.0).rem_euclid(size)-size/2.0
+}
@@ -0,0 +1,74 @@
+pub fn get_initial_state(grninit: &[u64], ngenes: usize) -> Vec<u8>{
+    // Expression state the dynamics start from (only the first gene on unless given)
+    if grninit.is_empty() {
+        (0..ngenes).map(|gene| (gene==0) as u8).collect()
+    } else {
+        grninit.iter().map(|on| *on as u8).collect()
+    }
+}
+
+
+pub fn get_attractor(seq: &[usize], weights: &[i64], init: &[u8]) -> String{
+    // Attractor reached from the initial state, as the expression states of its cycle (starting from the smallest) joined by >
+    let ngenes = init.len();
+    let mut history: Vec<Vec<u8>> = vec![init.to_vec()];
+
+    loop{
+        // Synchronous threshold update: gene i turns on if its summed input is positive, off if negative and stays otherwise
+        let state = history.last().unwrap();
+        let next: Vec<u8> = (0..ngenes).map(|i| {
+            let input: i64 = (0..ngenes).map(|j| weights[seq[i*ngenes+j]]*state[j] as i64).sum();
+            match input.signum(){
+                1 => 1,
+                -1 => 0,
+                _ => state[i],
+            }
+        }).collect();
+
+        if let Some(start) = history.iter().position(|past| *past==next) {
+            let cycle = &history[start..];
+            let first = (0..cycle.len()).min_by_key(|idx| &cycle[*idx]).unwrap();
+            let states: Vec<String> = (0..cycle.len()).map(|step| {
+                cycle[(first+step)%cycle.len()].iter().map(|on| on.to_string()).collect()
+            }).collect();
+            return states.join(">");
+        }
+        history.push(next);
+    }
+}
+
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    const WEIGHTS: [i64;3] = [-1,0,1]; // Letters 0, 1 and 2 are repression, no effect and activation
+
+    #[test]
+    fn initial_state(){
+        assert_eq!(get_initial_state(&[],3),vec![1,0,0]);
+        assert_eq!(get_initial_state(&[0,1,1],3),vec![0,1,1]);
+    }
+
+    #[test]
+    fn fixed_points(){
+        // Without interactions the initial state is kept
+        assert_eq!(get_attractor(&[1,1,1,1],&WEIGHTS,&[1,0]),"10");
+        // Gene 0 keeps itself on and turns gene 1 on: the transient state 10 is not part of the attractor
+        assert_eq!(get_attractor(&[2,1,2,1],&WEIGHTS,&[1,0]),"11");
+    }
+
+    #[test]
+    fn cycles_start_from_smallest_state(){
+        // Each gene represses itself and activates the other, so expression alternates between the genes
+        let seq = [0,2,2,0];
+        assert_eq!(get_attractor(&seq,&WEIGHTS,&[1,0]),"01>10");
+        assert_eq!(get_attractor(&seq,&WEIGHTS,&[0,1]),"01>10");
+        assert_eq!(get_attractor(&seq,&WEIGHTS,&[1,1]),"11");
+
+        // Ring of three genes that pass expression on: 100>010>001 is named from 001
+        let ring = [0,1,2,2,0,1,1,2,0];
+        assert_eq!(get_attractor(&ring,&WEIGHTS,&[1,0,0]),"001>100>010");
+        assert_eq!(get_attractor(&ring,&WEIGHTS,&[0,1,0]),"001>100>010");
+    }
+}
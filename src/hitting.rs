@@ -0,0 +1,127 @@
+use crate::gpm::Gpmap;
+use crate::pop::Population;
+
+
+
+#[derive(Clone)]
+pub struct HittingTimes{
+    pub pids: Vec<u64>,                 // Phenotypes tracked (in the order of the map's phenotype list)
+    pub produced: Vec<Option<u64>>,     // Generation at which each phenotype was first present (None if never)
+    pub threshold: Vec<Option<u64>>,    // Generation at which each phenotype first reached the frequency threshold (None if never)
+    pub endtime: u64,                   // Generation at which observation stopped (censoring time)
+}
+
+
+
+impl HittingTimes{
+    pub fn new(gpmap: &Gpmap) -> HittingTimes{
+        let pids = gpmap.get_pid_list().clone();
+        let npids = pids.len();
+        HittingTimes { pids, produced: vec![None; npids], threshold: vec![None; npids], endtime: 0 }
+    }
+
+    pub fn update(&mut self, time: u64, pop: &Population, gpmap: &Gpmap, hitfreq: f64){
+        let phenocounts = pop.get_pheno_counts(gpmap);
+        let total: f64 = phenocounts.iter().map(|(_,c)| c).sum();
+
+        for (idx,(_,count)) in phenocounts.iter().enumerate(){
+            if self.produced[idx].is_none() && *count>0.0 {
+                self.produced[idx] = Some(time);
+            }
+            if self.threshold[idx].is_none() && total>0.0 && count/total>=hitfreq {
+                self.threshold[idx] = Some(time);
+            }
+        }
+        self.endtime = time;
+    }
+}
+
+
+pub struct HittingSummary{
+    pub pid: u64,
+    pub event: &'static str,     // "produced" or "threshold"
+    pub nrepl: usize,            // Number of replicates
+    pub nhit: usize,             // Number of replicates in which the event happened before censoring
+    pub restrictedmean: f64,     // Mean hitting time, counting censored replicates at their censoring time
+    pub quantiles: Vec<Option<u64>>, // Hitting time quantiles (None if the quantile lies among censored replicates)
+}
+
+
+pub const QUANTILES: [f64;5] = [0.05,0.25,0.5,0.75,0.95];
+
+
+pub fn summarize(hittimes: &[HittingTimes]) -> Vec<HittingSummary>{
+    let mut summaries: Vec<HittingSummary> = Vec::new();
+    if hittimes.is_empty(){
+        return summaries;
+    }
+
+    for (idx,pid) in hittimes[0].pids.iter().enumerate(){
+        for event in ["produced","threshold"]{
+            // Hitting time (or censoring time) and whether the event happened in each replicate
+            let mut times: Vec<(u64,bool)> = hittimes.iter().map(|h| {
+                let hit = if event=="produced" { h.produced[idx] } else { h.threshold[idx] };
+                match hit {
+                    Some(time) => (time,true),
+                    None => (h.endtime,false),
+                }
+            }).collect();
+
+            let nrepl = times.len();
+            let nhit = times.iter().filter(|(_,hit)| *hit).count();
+            let restrictedmean = times.iter().map(|(time,_)| *time as f64).sum::<f64>()/nrepl as f64;
+
+            // Censored replicates are ranked after every observed hitting time
+            times.sort_by_key(|(time,hit)| (!hit,*time));
+            let quantiles = QUANTILES.iter().map(|q| {
+                let rank = ((q*nrepl as f64).ceil() as usize).clamp(1,nrepl)-1;
+                if times[rank].1 { Some(times[rank].0) } else { None }
+            }).collect();
+
+            summaries.push(HittingSummary { pid: *pid, event, nrepl, nhit, restrictedmean, quantiles });
+        }
+    }
+
+    summaries
+}
+
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn get_hittimes(produced: Option<u64>, endtime: u64) -> HittingTimes{
+        HittingTimes { pids: vec![7], produced: vec![produced], threshold: vec![None], endtime }
+    }
+
+    #[test]
+    fn censored_quantiles(){
+        // Two of four replicates hit, so quantiles above the median lie among censored replicates
+        let hittimes = vec![get_hittimes(Some(3),10),get_hittimes(None,10),get_hittimes(Some(1),10),get_hittimes(None,10)];
+        let summaries = summarize(&hittimes);
+        assert_eq!(summaries.len(),2);
+
+        let produced = &summaries[0];
+        assert_eq!((produced.pid,produced.event,produced.nrepl,produced.nhit),(7,"produced",4,2));
+        assert_eq!(produced.restrictedmean,6.0);
+        assert_eq!(produced.quantiles,vec![Some(1),Some(1),Some(3),None,None]);
+
+        let threshold = &summaries[1];
+        assert_eq!((threshold.event,threshold.nhit,threshold.restrictedmean),("threshold",0,10.0));
+        assert!(threshold.quantiles.iter().all(|q| q.is_none()));
+    }
+
+    #[test]
+    fn censoring_ranks_last(){
+        // A replicate censored early still ranks after every observed hitting time
+        let hittimes = vec![get_hittimes(Some(5),10),get_hittimes(None,2)];
+        let summaries = summarize(&hittimes);
+        assert_eq!(summaries[0].quantiles,vec![Some(5),Some(5),Some(5),None,None]);
+        assert_eq!(summaries[0].restrictedmean,3.5);
+    }
+
+    #[test]
+    fn no_replicates(){
+        assert!(summarize(&[]).is_empty());
+    }
+}
@@ -0,0 +1,240 @@
+use crate::config::Config;
+use std::error::Error;
+use std::fs::{self,File};
+use std::io::Write;
+use std::path::Path;
+
+pub const RESIDUES: [char;2] = ['H','P']; // Letters of HP sequences (hydrophobic and polar, in the order of their indices)
+const MOVES: [(char,i64,i64);4] = [('U',0,1),('R',1,0),('D',0,-1),('L',-1,0)];
+
+
+
+pub struct Fold{
+    pub moves: String,          // Lattice steps between consecutive residues (U, R, D or L)
+    contacts: Vec<u64>,         // Pairs of residues that are lattice neighbours without being chain neighbours (as bitmasks)
+}
+
+
+
+impl Fold{
+    fn from_moves(moves: &str) -> Option<Fold>{
+        // Walk the steps from the origin, rejecting unknown steps and walks that visit a site twice
+        let mut sites: Vec<(i64,i64)> = vec![(0,0)];
+        for step in moves.chars(){
+            let (_,dx,dy) = MOVES.iter().find(|(name,_,_)| *name==step)?;
+            let (x,y) = *sites.last().unwrap();
+            if sites.contains(&(x+dx,y+dy)) {
+                return None;
+            }
+            sites.push((x+dx,y+dy));
+        }
+
+        let mut contacts: Vec<u64> = Vec::new();
+        for (i,a) in sites.iter().enumerate(){
+            for (j,b) in sites.iter().enumerate().skip(i+2){
+                if (a.0-b.0).abs()+(a.1-b.1).abs()==1 {
+                    contacts.push((1<<i)|(1<<j));
+                }
+            }
+        }
+        Some(Fold { moves: moves.to_string(), contacts })
+    }
+
+    pub fn get_energy(&self, hmask: u64) -> i64{
+        // One unit of energy is gained for every contact between two H residues
+        -(self.contacts.iter().filter(|contact| *contact & hmask==**contact).count() as i64)
+    }
+}
+
+
+pub fn get_box(len: u64) -> Option<(u64,u64)>{
+    // Most square box of exactly len sites, at least 2 by 2
+    (2..=len).take_while(|w| w*w<=len).filter(|w| len.is_multiple_of(*w)).last().map(|w| (w,len/w))
+}
+
+
+pub fn get_folds(cfg: &Config) -> Result<Vec<Fold>,Box<dyn Error>>{
+    // Maximally compact folds of the chain, read from the cache file of this chain length or enumerated and cached
+    let cachepath = cfg.foldcachedir.join(format!("hpfolds_{}.csv",cfg.seqlen));
+    if cachepath.exists() {
+        return read_folds(&cachepath,cfg.seqlen);
+    }
+
+    let (width,height) = get_box(cfg.seqlen).ok_or(format!("seqlen {} does not fill a box of at least 2 by 2 sites",cfg.seqlen))?;
+    let folds = enumerate_folds(width as usize,height as usize);
+
+    fs::create_dir_all(&cfg.foldcachedir)?;
+    let mut cachefile = File::create(&cachepath)?;
+    for fold in folds.iter(){
+        cachefile.write_all(format!("{}\n",fold.moves).as_bytes())?;
+    }
+    Ok(folds)
+}
+
+
+fn read_folds(cachepath: &Path, len: u64) -> Result<Vec<Fold>,Box<dyn Error>>{
+    let content = fs::read_to_string(cachepath)?;
+    let mut folds: Vec<Fold> = Vec::new();
+    for (lineno,row) in content.lines().enumerate(){
+        let fold = Fold::from_moves(row.trim()).filter(|_| row.trim().len() as u64==len-1);
+        folds.push(fold.ok_or(format!("line {} of {} is not a fold of a chain of {} residues",lineno+1,cachepath.display(),len))?);
+    }
+    if folds.is_empty() {
+        return Err(format!("{} has no folds",cachepath.display()).into());
+    }
+    Ok(folds)
+}
+
+
+fn enumerate_folds(width: usize, height: usize) -> Vec<Fold>{
+    // Every walk through all sites of the box, keeping one of the walks related by rotations and reflections of the box
+    let nsites = width*height;
+    let mut folds: Vec<Fold> = Vec::new();
+
+    for start in 0..nsites{
+        let mut path: Vec<usize> = vec![start];
+        let mut visited: Vec<bool> = vec![false; nsites];
+        visited[start] = true;
+        extend_path(&mut path,&mut visited,width,height,&mut |path| {
+            if is_canonical(path,width,height) {
+                let moves: String = path.windows(2).map(|pair| get_move(pair[0],pair[1],width)).collect();
+                folds.push(Fold::from_moves(&moves).unwrap());
+            }
+        });
+    }
+
+    folds
+}
+
+
+fn extend_path(path: &mut Vec<usize>, visited: &mut Vec<bool>, width: usize, height: usize, found: &mut impl FnMut(&[usize])){
+    if path.len()==visited.len() {
+        found(path);
+        return;
+    }
+
+    let site = *path.last().unwrap();
+    let (x,y) = (site%width,site/width);
+    let mut next: Vec<usize> = Vec::new();
+    if y+1<height { next.push(site+width); }
+    if x+1<width { next.push(site+1); }
+    if y>0 { next.push(site-width); }
+    if x>0 { next.push(site-1); }
+
+    for nsite in next{
+        if !visited[nsite] {
+            visited[nsite] = true;
+            path.push(nsite);
+            extend_path(path,visited,width,height,found);
+            path.pop();
+            visited[nsite] = false;
+        }
+    }
+}
+
+
+fn is_canonical(path: &[usize], width: usize, height: usize) -> bool{
+    // Is the walk the smallest (comparing sites in order) of its images under the symmetries of the box?
+    let nsymmetries = if width==height { 8 } else { 4 };
+    (1..nsymmetries).all(|symmetry| {
+        let image = path.iter().map(|site| get_image(*site,symmetry,width,height));
+        path.iter().copied().cmp(image).is_le()
+    })
+}
+
+
+fn get_image(site: usize, symmetry: usize, width: usize, height: usize) -> usize{
+    // Site after a reflection or rotation of the box (the ones from 4 on swap the axes and need a square box)
+    let (x,y) = (site%width,site/width);
+    let (ix,iy) = match symmetry{
+        1 => (width-1-x,y),
+        2 => (x,height-1-y),
+        3 => (width-1-x,height-1-y),
+        4 => (y,x),
+        5 => (width-1-y,x),
+        6 => (y,height-1-x),
+        7 => (width-1-y,height-1-x),
+        _ => (x,y),
+    };
+    iy*width+ix
+}
+
+
+fn get_move(from: usize, to: usize, width: usize) -> char{
+    if to==from+width {
+        'U'
+    } else if to==from+1 {
+        'R'
+    } else if to+width==from {
+        'D'
+    } else {
+        'L'
+    }
+}
+
+
+pub fn fold(seq: &[usize], folds: &[Fold]) -> String{
+    // Fold with the unique lowest energy, or "unfolded" if several folds share it
+    let hmask: u64 = seq.iter().enumerate().filter(|(_,res)| RESIDUES[**res]=='H').map(|(i,_)| 1<<i).sum();
+
+    let mut best: Option<&Fold> = None;
+    let mut bestenergy: i64 = i64::MAX;
+    for fold in folds.iter(){
+        let energy = fold.get_energy(hmask);
+        if energy<bestenergy {
+            best = Some(fold);
+            bestenergy = energy;
+        } else if energy==bestenergy {
+            best = None;
+        }
+    }
+
+    match best{
+        Some(fold) => fold.moves.clone(),
+        None => String::from("unfolded"),
+    }
+}
+
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn fold_counts(){
+        // Compact folds of 3x3, 4x4 and 4x5 boxes up to the symmetries of the box
+        assert_eq!(enumerate_folds(3,3).len(),5);
+        assert_eq!(enumerate_folds(4,4).len(),69);
+        assert_eq!(enumerate_folds(4,5).len(),503);
+    }
+
+    #[test]
+    fn one_fold_per_symmetry_class(){
+        // Every walk through the box has exactly one image among the canonical folds
+        for (width,height) in [(3,3),(4,4),(4,5)]{
+            let nsymmetries = if width==height { 8 } else { 4 };
+            let mut classes: HashSet<Vec<usize>> = HashSet::new();
+            for start in 0..width*height{
+                let mut path: Vec<usize> = vec![start];
+                let mut visited: Vec<bool> = vec![false; width*height];
+                visited[start] = true;
+                extend_path(&mut path,&mut visited,width,height,&mut |path| {
+                    let smallest = (0..nsymmetries).map(|symmetry| path.iter().map(|site| get_image(*site,symmetry,width,height)).collect::<Vec<usize>>()).min().unwrap();
+                    classes.insert(smallest);
+                });
+            }
+            let folds = enumerate_folds(width,height);
+            let moves: HashSet<&String> = folds.iter().map(|fold| &fold.moves).collect();
+            assert_eq!(moves.len(),folds.len());
+            assert_eq!(classes.len(),folds.len());
+        }
+    }
+
+    #[test]
+    fn ties_are_unfolded(){
+        // Without H residues every fold has zero energy
+        let folds = enumerate_folds(3,3);
+        assert_eq!(fold(&[1; 9],&folds),"unfolded");
+    }
+}
@@ -3,4 +3,12 @@ pub mod output;
 pub mod environment;
 pub mod gpm;
 pub mod pop;
-pub mod evolve;
\ No newline at end of file
+pub mod evolve;
+pub mod observer;
+pub mod lineage;
+pub mod hitting;
+pub mod analysis;
+pub mod mapgen;
+pub mod rna;
+pub mod hp;
+pub mod grn;
\ No newline at end of file
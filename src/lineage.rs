@@ -0,0 +1,177 @@
+use crate::pop::Population;
+use std::collections::HashMap;
+
+
+
+pub struct LineageNode{
+    pub parent: Option<u64>, // Id of the node this genotype mutated from (None for founders)
+    pub x: u64,              // Genotype x
+    pub y: u64,              // Genotype y
+    pub level: usize,        // Mutation rate modifier level
+    pub time: u64,           // Generation at which this genotype arose on this lineage
+    count: u64,              // Number of living individuals belonging to this node
+    nchildren: u64,          // Number of nodes (alive or with living descendants) that descend directly from this node
+}
+
+
+pub struct Lineage{
+    nodes: HashMap<u64,LineageNode>, // All nodes with living individuals or living descendants
+    nextid: u64,                     // Id to be given to the next created node
+    newnodes: HashMap<(u64,u64,u64,usize),u64>, // Nodes created in the current generation, keyed by (parent,x,y,level)
+}
+
+
+
+impl Lineage{
+    pub fn new(pop: &Population) -> Lineage{
+        let mut lineage = Lineage { nodes: HashMap::new(), nextid: 0, newnodes: HashMap::new() };
+
+        // Every occupied class in the initial population is the founder of its own lineage
+        for (x,y,level,size) in pop.get_classes(){
+            let id = lineage.nextid;
+            lineage.nodes.insert(id, LineageNode { parent: None, x, y, level, time: 0, count: size as u64, nchildren: 0 });
+            lineage.nextid += 1;
+        }
+
+        lineage
+    }
+
+    pub fn get_living(&self) -> Vec<(u64,u64,u64,usize,u64)>{
+        // Returns (id,x,y,level,count) for every node with living individuals
+        let mut living: Vec<(u64,u64,u64,usize,u64)> = self.nodes.iter()
+            .filter(|(_,node)| node.count!=0)
+            .map(|(id,node)| (*id,node.x,node.y,node.level,node.count))
+            .collect();
+        living.sort();
+        living
+    }
+
+    pub fn add_offspring(&mut self, parent: u64, x: u64, y: u64, level: usize, time: u64) -> u64{
+        // Offspring with the parental genotype (and modifier) stay on the parent node, mutants start a new node
+        let parent_node = &self.nodes[&parent];
+        if parent_node.x==x && parent_node.y==y && parent_node.level==level {
+            return parent;
+        }
+
+        // All mutants from the same parent to the same class in a generation share a node
+        if let Some(id) = self.newnodes.get(&(parent,x,y,level)){
+            return *id;
+        }
+
+        let id = self.nextid;
+        self.nextid += 1;
+        self.nodes.insert(id, LineageNode { parent: Some(parent), x, y, level, time, count: 0, nchildren: 0 });
+        self.nodes.get_mut(&parent).unwrap().nchildren += 1;
+        self.newnodes.insert((parent,x,y,level),id);
+        id
+    }
+
+    pub fn set_counts(&mut self, counts: &HashMap<u64,u64>){
+        self.newnodes.clear();
+
+        for (id,node) in self.nodes.iter_mut(){
+            node.count = *counts.get(id).unwrap_or(&0);
+        }
+
+        // Prune extinct branches: remove nodes without living individuals or descendants, walking up towards the founders
+        let extinct: Vec<u64> = self.nodes.iter()
+            .filter(|(_,node)| node.count==0 && node.nchildren==0)
+            .map(|(id,_)| *id)
+            .collect();
+
+        for id in extinct{
+            let mut current = Some(id);
+            while let Some(cid) = current{
+                let node = &self.nodes[&cid];
+                if node.count!=0 || node.nchildren!=0 {
+                    break;
+                }
+                let parent = node.parent;
+                self.nodes.remove(&cid);
+                if let Some(pid) = parent{
+                    self.nodes.get_mut(&pid).unwrap().nchildren -= 1;
+                }
+                current = parent;
+            }
+        }
+    }
+
+    pub fn get_line_of_descent(&self, x: u64, y: u64) -> Vec<&LineageNode>{
+        // Start from the most abundant living node with the given genotype and trace back to its founder
+        let tip = self.nodes.iter()
+            .filter(|(_,node)| node.x==x && node.y==y && node.count!=0)
+            .max_by_key(|(id,node)| (node.count,std::cmp::Reverse(**id)))
+            .map(|(id,_)| *id);
+
+        let mut line: Vec<&LineageNode> = Vec::new();
+        let mut current = tip;
+        while let Some(cid) = current{
+            let node = &self.nodes[&cid];
+            line.push(node);
+            current = node.parent;
+        }
+        line.reverse();
+        line
+    }
+}
+
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::config::Config;
+
+    fn get_founders() -> Lineage{
+        // Founder 0 at (0,0) with two individuals and founder 1 at (1,1) with one
+        let cfg = Config { grid_x: 2, grid_y: 2, ..Default::default() };
+        let pop = Population::from_grid(&cfg,vec![vec![2.0,0.0],vec![0.0,1.0]]).unwrap();
+        Lineage::new(&pop)
+    }
+
+    #[test]
+    fn pruning_keeps_ancestors(){
+        let mut lineage = get_founders();
+        assert_eq!(lineage.get_living(),vec![(0,0,0,0,2),(1,1,1,0,1)]);
+
+        // Founder 0 leaves only a mutant at (0,1) and founder 1 dies out
+        let child = lineage.add_offspring(0,0,1,0,1);
+        lineage.set_counts(&HashMap::from([(child,1)]));
+        assert_eq!(lineage.nodes.len(),2);
+        assert!(lineage.nodes.contains_key(&0));
+        assert_eq!(lineage.get_living(),vec![(child,0,1,0,1)]);
+
+        // The mutant leaves only a mutant at (1,0): both ancestors stay on the line of descent
+        let grandchild = lineage.add_offspring(child,1,0,0,2);
+        lineage.set_counts(&HashMap::from([(grandchild,1)]));
+        assert_eq!(lineage.nodes.len(),3);
+        let line: Vec<(u64,u64,u64)> = lineage.get_line_of_descent(1,0).iter().map(|node| (node.x,node.y,node.time)).collect();
+        assert_eq!(line,vec![(0,0,0),(0,1,1),(1,0,2)]);
+    }
+
+    #[test]
+    fn pruning_removes_extinct_branches(){
+        // A side branch that dies out is removed up to the node it split from
+        let mut lineage = get_founders();
+        let stem = lineage.add_offspring(0,0,1,0,1);
+        let branch = lineage.add_offspring(0,1,0,0,1);
+        lineage.set_counts(&HashMap::from([(stem,1),(branch,1)]));
+        let tip = lineage.add_offspring(branch,1,1,0,2);
+        lineage.set_counts(&HashMap::from([(stem,2),(tip,0)]));
+
+        assert_eq!(lineage.nodes.len(),2);
+        assert_eq!(lineage.nodes[&0].nchildren,1);
+        assert!(lineage.nodes.contains_key(&stem));
+        assert!(!lineage.nodes.contains_key(&branch));
+    }
+
+    #[test]
+    fn mutants_share_nodes(){
+        // Offspring with the parental class stay on the parent node, mutants to the same class in a generation share a node
+        let mut lineage = get_founders();
+        assert_eq!(lineage.add_offspring(0,0,0,0,1),0);
+        let mutant = lineage.add_offspring(0,0,1,0,1);
+        assert_eq!(lineage.add_offspring(0,0,1,0,1),mutant);
+        lineage.set_counts(&HashMap::from([(0,1),(mutant,2)]));
+        assert_ne!(lineage.add_offspring(0,0,1,0,2),mutant);
+    }
+}
@@ -4,6 +4,8 @@ use simgpm::environment::Environment;
 use simgpm::gpm::Gpmap;
 use simgpm::pop::Population;
 use simgpm::evolve::Simulation;
+use simgpm::analysis::{PhenoNet,Components};
+use simgpm::mapgen;
 use std::fs;
 use std::io::Write;
 use std::process;
@@ -26,14 +28,63 @@ fn main() {
     // Create an output directory
     fs::create_dir_all("output").unwrap();
 
+    // Generating a map does not read any config files
+    if cfg.mode=="genmap" {
+        let mut logfile = fs::File::create("output/cmd.log").unwrap();
+        logfile.write_all(format!("{}",cfg).as_bytes()).unwrap();
+
+        print!("Done!\nGenerating genotype-phenotype map.. ");
+        let written = mapgen::generate_map(&cfg).and_then(|grid| mapgen::write_map(&grid,"./output"));
+        written.unwrap_or_else(|err|{
+            println!("Could not generate the map: {}",err);
+            process::exit(1);
+        });
+        println!("Done!");
+        return;
+    }
+
     // Create a copy of all config files in output directory
-    fs::copy(&cfg.gpfilepath,"./output/gpm.csv").unwrap();
+    if cfg.gpmtype=="file"{
+        fs::copy(&cfg.gpfilepath,"./output/gpm.csv").unwrap();
+    }
 
     fs::copy(&cfg.envfilepath,"./output/env.cfg").unwrap();
 
+    if !cfg.biasfilepath.as_os_str().is_empty(){
+        fs::copy(&cfg.biasfilepath,"./output/bias.csv").unwrap();
+    }
+
+    if cfg.initpop=="file"{
+        fs::copy(&cfg.initfilepath,"./output/initpop.csv").unwrap();
+    }
+
     // Create a cmd.log in output directory (containing all parameters used)
     let mut logfile = fs::File::create("output/cmd.log").unwrap();
-    logfile.write(format!("{}",cfg).as_bytes()).unwrap();
+    logfile.write_all(format!("{}",cfg).as_bytes()).unwrap();
+
+    // Analyses of the map only need the map
+    if cfg.mode=="phenonet" || cfg.mode=="components" {
+        print!("Done!\nParsing genotype-phenotype map.. ");
+        let gpmap: Gpmap = Gpmap::parse_gpmap(&cfg).unwrap_or_else(|err|{
+            println!("Could not parse the gpmap file: {}",err);
+            process::exit(1);
+        });
+        write_phenotypes(&gpmap);
+        println!("Done!");
+
+        print!("Writing {}.. ",cfg.mode);
+        let written = if cfg.mode=="phenonet" {
+            PhenoNet::new(&cfg,&gpmap).write_files("./output")
+        } else {
+            Components::new(&cfg,&gpmap).write_files("./output")
+        };
+        written.unwrap_or_else(|err|{
+            println!("Could not write the analysis of the map: {}",err);
+            process::exit(1);
+        });
+        println!("Done!");
+        return;
+    }
 
     // Create output files based on config (hdf5 format)
     let output = Output::createfiles(&cfg).unwrap_or_else(|err|{
@@ -48,6 +99,10 @@ fn main() {
         println!("Could not parse the environment file: {}",err);
         process::exit(1);
     });
+    for pfpath in env.get_pfpaths(){
+        let pffname = pfpath.file_name().unwrap().to_str().unwrap();
+        fs::copy(pfpath,format!("./output/{pffname}")).unwrap();
+    }
     println!("Done!");
 
     print!("Parsing genotype-phenotype map.. ");
@@ -56,12 +111,13 @@ fn main() {
         println!("Could not parse the gpmap file: {}",err);
         process::exit(1);
     });
+    write_phenotypes(&gpmap);
     println!("Done!");
 
 
     print!("Generating initial population.. ");
     // Parse/create initial population file
-    let pop: Population = Population::gen_uniform(&cfg).unwrap_or_else(|err| {
+    let pop: Population = Population::generate(&cfg,&gpmap).unwrap_or_else(|err| {
         println!("Could not generate initial population!: {}", err);
         process::exit(1);
     });
@@ -69,12 +125,26 @@ fn main() {
 
     print!("Running simulation...");
     // Run simulation(s) and save output files
-    let sim: Simulation = Simulation::setup(cfg,output,env,gpmap,pop).unwrap_or_else(|err|{
+    let mut sim: Simulation = Simulation::setup(cfg,output,env,gpmap,pop).unwrap_or_else(|err|{
         println!("Could not setup the simulation!: {}",err);
         process::exit(1);
     });
-    sim.run();
+    sim.run_replicates().unwrap_or_else(|err|{
+        println!("Simulation failed: {}",err);
+        process::exit(1);
+    });
     println!("Done!");
     
 
 }
+
+
+fn write_phenotypes(gpmap: &Gpmap){
+    // Built-in maps name the phenotypes they assign ids to
+    if !gpmap.get_pheno_names().is_empty(){
+        gpmap.write_phenotypes("./output").unwrap_or_else(|err|{
+            println!("Could not write the phenotypes of the map: {}",err);
+            process::exit(1);
+        });
+    }
+}
@@ -0,0 +1,174 @@
+use crate::config::Config;
+use crate::gpm;
+use std::error::Error;
+use std::f64::consts::PI;
+use std::fs::File;
+use std::io::Write;
+use rand::prelude::*;
+use rand::distributions::WeightedIndex;
+use rand::rngs::StdRng;
+
+
+
+pub fn generate_map(cfg: &Config) -> Result<Vec<Vec<u64>>,Box<dyn Error>>{
+    // Synthetic gp-map (phenotype ids indexed by x and y) of the family chosen with --maptype
+    let mut rng = if cfg.mapseed<0 { StdRng::from_entropy() } else { StdRng::seed_from_u64(cfg.mapseed as u64) };
+
+    // Phenotype frequencies (equal unless given)
+    let freqs: Vec<f64> = if cfg.mapfreqs.is_empty() { vec![1.0; cfg.mapnpheno as usize] } else { cfg.mapfreqs.clone() };
+    if freqs.len()!=cfg.mapnpheno as usize || freqs.iter().any(|f| *f<0.0) || freqs.iter().sum::<f64>()<=0.0 {
+        return Err("mapfreqs should be mapnpheno non-negative values with a positive sum".into());
+    }
+    let phenodist = WeightedIndex::new(&freqs)?;
+
+    let (grid_x,grid_y) = (cfg.grid_x as usize,cfg.grid_y as usize);
+    let mut grid: Vec<Vec<u64>> = vec![vec![0; grid_y]; grid_x];
+
+    match cfg.maptype.as_str(){
+        // Independent phenotypes drawn with mapfreqs
+        "random" => {
+            grid.iter_mut().for_each(|row| row.iter_mut().for_each(|pid| *pid = phenodist.sample(&mut rng) as u64));
+        },
+
+        // Every genotype takes the phenotype of its closest of mapcells random centres
+        "voronoi" => {
+            let centres: Vec<(f64,f64,u64)> = (0..cfg.mapcells).map(|_| {
+                (rng.gen::<f64>()*grid_x as f64,rng.gen::<f64>()*grid_y as f64,phenodist.sample(&mut rng) as u64)
+            }).collect();
+            for (idx,row) in grid.iter_mut().enumerate(){
+                for (idy,pid) in row.iter_mut().enumerate(){
+                    let closest = centres.iter().min_by(|a,b| {
+                        get_torus_dist2(idx,idy,a.0,a.1,grid_x,grid_y).total_cmp(&get_torus_dist2(idx,idy,b.0,b.1,grid_x,grid_y))
+                    }).unwrap();
+                    *pid = closest.2;
+                }
+            }
+        },
+
+        // Bands of mapwidth genotypes along x, cycling through the phenotypes
+        "stripes" => {
+            for (idx,row) in grid.iter_mut().enumerate(){
+                row.iter_mut().for_each(|pid| *pid = ((idx/cfg.mapwidth as usize)%cfg.mapnpheno as usize) as u64);
+            }
+        },
+
+        // Squares of mapwidth by mapwidth genotypes, cycling through the phenotypes
+        "checker" => {
+            for (idx,row) in grid.iter_mut().enumerate(){
+                for (idy,pid) in row.iter_mut().enumerate(){
+                    *pid = ((idx/cfg.mapwidth as usize+idy/cfg.mapwidth as usize)%cfg.mapnpheno as usize) as u64;
+                }
+            }
+        },
+
+        // Site percolation: genotypes are occupied with probability mapoccupancy and each cluster of occupied neighbours is its own phenotype (0 is unoccupied)
+        "percolation" => {
+            let occupied: Vec<Vec<bool>> = (0..grid_x).map(|_| (0..grid_y).map(|_| rng.gen::<f64>()<cfg.mapoccupancy).collect()).collect();
+            let mut nclusters: u64 = 0;
+            for startx in 0..grid_x{
+                for starty in 0..grid_y{
+                    if !occupied[startx][starty] || grid[startx][starty]!=0 {
+                        continue;
+                    }
+                    nclusters += 1;
+                    grid[startx][starty] = nclusters;
+                    let mut stack: Vec<(usize,usize)> = vec![(startx,starty)];
+                    while let Some((x,y)) = stack.pop(){
+                        for (nx,ny) in [(x,(y+1)%grid_y),((x+1)%grid_x,y),(x,(y+grid_y-1)%grid_y),((x+grid_x-1)%grid_x,y)]{
+                            if occupied[nx][ny] && grid[nx][ny]==0 {
+                                grid[nx][ny] = nclusters;
+                                stack.push((nx,ny));
+                            }
+                        }
+                    }
+                }
+            }
+        },
+
+        // mapnpheno-1 blobs with rough edges nested around a random centre, the phenotype is the number of blobs containing the genotype
+        "blobs" => {
+            let (cx,cy) = (rng.gen::<f64>()*grid_x as f64,rng.gen::<f64>()*grid_y as f64);
+            let maxradius = 0.45*grid_x.min(grid_y) as f64;
+            let harmonics: Vec<(f64,f64)> = (1..=4).map(|_| (0.1*rng.gen::<f64>(),2.0*PI*rng.gen::<f64>())).collect();
+
+            for (idx,row) in grid.iter_mut().enumerate(){
+                for (idy,pid) in row.iter_mut().enumerate(){
+                    let dx = gpm::get_wrapped_delta(cx,idx as f64,grid_x as f64);
+                    let dy = gpm::get_wrapped_delta(cy,idy as f64,grid_y as f64);
+                    let angle = dy.atan2(dx);
+                    let roughness: f64 = harmonics.iter().enumerate().map(|(m,(amp,phase))| amp*((m+1) as f64*angle+phase).cos()).sum();
+                    let dist = (dx*dx+dy*dy).sqrt()/(1.0+roughness);
+
+                    *pid = (1..cfg.mapnpheno).filter(|k| dist<maxradius*(1.0-(k-1) as f64/(cfg.mapnpheno-1) as f64)).count() as u64;
+                }
+            }
+        },
+
+        _ => return Err(format!("unknown map type {}",cfg.maptype).into()),
+    }
+
+    Ok(grid)
+}
+
+
+pub fn write_map(grid: &[Vec<u64>], dir: &str) -> Result<(),Box<dyn Error>>{
+    // gp-map file (one row per y) and a template pf file giving every phenotype a fitness of 1
+    let mut gpmfile = File::create(format!("{}/gpm.csv",dir))?;
+    for idy in 0..grid[0].len(){
+        let row: Vec<String> = grid.iter().map(|col| col[idy].to_string()).collect();
+        gpmfile.write_all(format!("{}\n",row.join(",")).as_bytes())?;
+    }
+
+    let maxpid = *grid.iter().flatten().max().unwrap();
+    let mut pffile = File::create(format!("{}/pf.csv",dir))?;
+    for pid in 0..=maxpid{
+        pffile.write_all(format!("{},1.0\n",pid).as_bytes())?;
+    }
+
+    Ok(())
+}
+
+
+fn get_torus_dist2(x: usize, y: usize, cx: f64, cy: f64, grid_x: usize, grid_y: usize) -> f64{
+    let dx = gpm::get_wrapped_delta(cx,x as f64,grid_x as f64);
+    let dy = gpm::get_wrapped_delta(cy,y as f64,grid_y as f64);
+    dx*dx+dy*dy
+}
+
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn get_config(maptype: &str, mapseed: i64) -> Config{
+        Config { grid_x: 12, grid_y: 8, maptype: String::from(maptype), mapnpheno: 3, mapwidth: 2, mapseed, ..Default::default() }
+    }
+
+    #[test]
+    fn same_seed_same_map(){
+        for maptype in ["random","voronoi","percolation","blobs"]{
+            let grid = generate_map(&get_config(maptype,42)).unwrap();
+            assert_eq!(grid,generate_map(&get_config(maptype,42)).unwrap());
+            assert_eq!((grid.len(),grid[0].len()),(12,8));
+        }
+        assert_ne!(generate_map(&get_config("random",42)).unwrap(),generate_map(&get_config("random",43)).unwrap());
+    }
+
+    #[test]
+    fn stripes(){
+        // Bands of two genotypes along x cycling through phenotypes 0, 1 and 2
+        let grid = generate_map(&get_config("stripes",-1)).unwrap();
+        let expected: Vec<u64> = vec![0,0,1,1,2,2,0,0,1,1,2,2];
+        for (idx,row) in grid.iter().enumerate(){
+            assert!(row.iter().all(|pid| *pid==expected[idx]));
+        }
+    }
+
+    #[test]
+    fn checker(){
+        let grid = generate_map(&get_config("checker",-1)).unwrap();
+        assert_eq!(grid[0][..6],[0,0,1,1,2,2]);
+        assert_eq!(grid[2][..6],[1,1,2,2,0,0]);
+        assert_eq!(grid[5][7],(2+3)%3);
+    }
+}
@@ -0,0 +1,61 @@
+use crate::config::Config;
+use crate::environment::Environment;
+use crate::gpm::Gpmap;
+use crate::pop::Population;
+use crate::output;
+
+
+
+pub struct Observation<'a>{
+    pub config: &'a Config,         // Parameters of the run (including the replicate id)
+    pub time: u64,                  // Current generation
+    pub last: bool,                 // Is this the final generation of the run?
+    pub envid: i32,                 // Current environment
+    pub env: &'a Environment,       // Every defined environment
+    pub pop: &'a Population,        // Population after selection and mutation in this generation
+    pub gpmap: &'a Gpmap,           // Genotype-phenotype map
+    pub gfmap: &'a [Vec<f64>],      // Fitness of every genotype in the current environment
+}
+
+
+pub trait Observer{
+    // Called at generation 0, every saveevery generations and at the final generation of each run
+    fn observe(&mut self, obs: &Observation);
+
+    // Return true to be called in every generation instead
+    fn every_generation(&self) -> bool {
+        false
+    }
+}
+
+
+#[derive(Clone,Debug)]
+pub struct Record{
+    pub replid: u64,                    // Replicate id of the run
+    pub time: u64,                      // Generation
+    pub envid: i32,                     // Environment in this generation
+    pub domx: u64,                      // Dominant genotype
+    pub domy: u64,
+    pub domabund: f64,                  // Abundance of the dominant genotype
+    pub meanfitness: f64,               // Mean fitness in the current environment
+    pub meanmutrate: f64,               // Mean mutation probability
+    pub phenocounts: Vec<(u64,f64)>,    // Abundance of every phenotype of the map
+}
+
+
+impl Record{
+    pub fn from_observation(obs: &Observation) -> Record{
+        let (domx,domy,domabund) = obs.pop.get_dominance_info();
+        Record {
+            replid: obs.config.replid,
+            time: obs.time,
+            envid: obs.envid,
+            domx: domx as u64,
+            domy: domy as u64,
+            domabund,
+            meanfitness: output::get_mean_fitness(obs.pop,obs.gfmap),
+            meanmutrate: obs.pop.get_mean_mutrate(),
+            phenocounts: obs.pop.get_pheno_counts(obs.gpmap),
+        }
+    }
+}
@@ -1,19 +1,29 @@
 
 use crate::config::Config;
-use crate::gpm::Gpmap;
+use crate::gpm::{self,Gpmap,Space};
 use crate::pop::Population;
+use crate::lineage::Lineage;
+use crate::hitting::{self,HittingTimes,HittingSummary};
+use crate::evolve::RunSummary;
+use crate::observer::{Observer,Observation};
+use crate::analysis::Components;
 use std::error::Error;
 use std::fs::File;
 use std::io::Write;
+use std::collections::HashMap;
+
+const MAXALTMUTTABLE: u64 = 50_000_000; // Most genotype-phenotype probabilities kept per mutation rate by outaltmut (400 MB)
 
 #[derive(Default)]
 pub struct Output{
-    pub domfile: Option<File>,
-    pub evolfile: Option<File>,
-    pub envfile: Option<File>,
-    pub altmutfile: Option<File>,
-    pub popfile: Option<File>,
-    pub avgfile: Option<File>
+    pub lineagefile: Option<File>,
+    pub lineagephenofile: Option<File>,
+    pub extinctfile: Option<File>,
+    pub summaryfile: Option<File>,
+    pub invasionfile: Option<File>,
+    pub hittingfile: Option<File>,
+    pub hittingsummaryfile: Option<File>,
+    pub mutrateenvfile: Option<File>
 
 }
 
@@ -23,142 +33,539 @@ impl Output{
     pub fn createfiles(config: &Config) -> Result<Output, Box<dyn Error>>{
         let mut output = Output::default();
 
-        if config.outdom{
-            output.domfile = Some(File::create("./output/dominant.csv")?);
-            output.domfile.as_ref().unwrap().write(b"repl,time,domx,domy,abundance\n").unwrap();
+        if config.outextinct{
+            output.extinctfile = Some(File::create("./output/extinction.csv")?);
+            output.extinctfile.as_ref().unwrap().write_all(b"repl,time,envid,action\n").unwrap();
         }
-        if config.outevol{
-            output.evolfile = Some(File::create("./output/evolvability.csv")?);
-            output.evolfile.as_ref().unwrap().write(b"repl,time,popevol\n").unwrap(); 
+        if config.outsummary{
+            output.summaryfile = Some(File::create("./output/summary.csv")?);
+            output.summaryfile.as_ref().unwrap().write_all(b"repl,time,reason\n").unwrap();
         }
-        if config.outenv{
-            output.envfile = Some(File::create("./output/environment.csv")?);
-            output.envfile.as_ref().unwrap().write(b"repl,time,envid\n").unwrap();
+        if config.outinvasion && config.mode=="invasion"{
+            output.invasionfile = Some(File::create("./output/invasion.csv")?);
+            output.invasionfile.as_ref().unwrap().write_all(b"envid,mutx,muty,mutpid,k,popsize,ntrials,nfixed,nlost,nunresolved,fixprob,cilow,cihigh,neutral\n").unwrap();
         }
-        if config.outaltmut{
-            output.altmutfile = Some(File::create("./output/altmut.csv")?);
-            output.altmutfile.as_ref().unwrap().write(b"repl,time,pid,probmut\n").unwrap();
+        if config.outhitting{
+            output.hittingfile = Some(File::create("./output/hitting.csv")?);
+            output.hittingfile.as_ref().unwrap().write_all(b"repl,pid,event,time,censored\n").unwrap();
+            output.hittingsummaryfile = Some(File::create("./output/hittingsummary.csv")?);
+            let qnames: Vec<String> = hitting::QUANTILES.iter().map(|q| format!("q{}",q*100.0)).collect();
+            output.hittingsummaryfile.as_ref().unwrap().write_all(format!("pid,event,nrepl,nhit,restrictedmean,{}\n",qnames.join(",")).as_bytes()).unwrap();
         }
-        if config.outpopsave{
-            output.popfile = Some(File::create("./output/population.csv")?);
-            output.popfile.as_ref().unwrap().write(b"repl,time,x,y,pop\n").unwrap();
+        if config.outmutrate && config.evolmut{
+            output.mutrateenvfile = Some(File::create("./output/mutrateenv.csv")?);
+            output.mutrateenvfile.as_ref().unwrap().write_all(b"repl,envid,gens,meanmutrate\n").unwrap();
         }
-        if config.outtimeavg{
-            output.avgfile = Some(File::create("./output/timeavg.csv")?);
-            output.avgfile.as_ref().unwrap().write(b"repl,x,y,pop\n").unwrap();
+        if config.outlineage{
+            output.lineagefile = Some(File::create("./output/lineage.csv")?);
+            output.lineagefile.as_ref().unwrap().write_all(b"repl,time,x,y,pid\n").unwrap();
+            output.lineagephenofile = Some(File::create("./output/lineagepheno.csv")?);
+            output.lineagephenofile.as_ref().unwrap().write_all(b"repl,time,pid\n").unwrap();
         }
 
         Ok(output)
     }
 
-    pub fn generate_output(&self,time: u64,config: &Config,envid: i32, pop: &Population, gpmap: &Gpmap){
-        // Write dominant file
-        if !self.domfile.is_none(){
-            self.write_domfile(config,time,pop);
+    pub fn write_extinctfile(&self, config: &Config, time: u64, envid: i32){
+        self.extinctfile.as_ref().unwrap().write_all(format!("{},{},{},{}\n",config.replid,time,envid,config.onextinct).as_bytes()).unwrap();
+    }
+
+    pub fn write_summaryfile(&self, config: &Config, time: u64, reason: &str){
+        self.summaryfile.as_ref().unwrap().write_all(format!("{},{},{}\n",config.replid,time,reason).as_bytes()).unwrap();
+    }
+
+    pub fn write_invasionfile(&self, config: &Config, gpmap: &Gpmap, summaries: &[RunSummary]){
+        let (mutx,muty) = (config.invadexy[0],config.invadexy[1]);
+        let mutpid = gpmap.get_pid_at(mutx,muty);
+        let neutral = config.invadek as f64/config.popsize as f64;
+
+        // One row over all trials ("all") and one row per starting environment
+        let mut envids: Vec<i32> = summaries.iter().map(|s| s.startenvid).collect();
+        envids.sort();
+        envids.dedup();
+        let mut groups: Vec<(String,Vec<&RunSummary>)> = vec![(String::from("all"),summaries.iter().collect())];
+        for envid in envids{
+            groups.push((envid.to_string(),summaries.iter().filter(|s| s.startenvid==envid).collect()));
         }
 
-        // Write environment file
-        if !self.envfile.is_none(){
-            self.write_envfile(config,time,envid);
+        for (envid,trials) in groups.iter(){
+            // Trials that ran through an environment change are unresolved in the rows of single environments
+            let pooled = envid=="all";
+            let nfixed = trials.iter().filter(|s| s.stopreason=="fixed" && (pooled || s.nswitches==0)).count();
+            let nlost = trials.iter().filter(|s| s.stopreason=="lost" && (pooled || s.nswitches==0)).count();
+            let nunresolved = trials.len()-nfixed-nlost;
+
+            // Fixation probability among resolved trials, with a 95% Wilson score interval
+            let n = (nfixed+nlost) as f64;
+            let (fixprob,cilow,cihigh) = if n>0.0 {
+                let p = nfixed as f64/n;
+                let z: f64 = 1.96;
+                let centre = (p+z*z/(2.0*n))/(1.0+z*z/n);
+                let halfwidth = z*(p*(1.0-p)/n+z*z/(4.0*n*n)).sqrt()/(1.0+z*z/n);
+                (p,(centre-halfwidth).max(0.0),(centre+halfwidth).min(1.0))
+            } else {
+                (f64::NAN,f64::NAN,f64::NAN)
+            };
+
+            self.invasionfile.as_ref().unwrap().write_all(format!("{},{},{},{},{},{},{},{},{},{},{},{},{},{}\n",
+                envid,mutx,muty,mutpid,config.invadek,config.popsize,trials.len(),nfixed,nlost,nunresolved,fixprob,cilow,cihigh,neutral).as_bytes()).unwrap();
+        }
+    }
+
+    pub fn write_hittingfile(&self, config: &Config, hittimes: &HittingTimes){
+        // Censored events are reported at the censoring time
+        for (idx,pid) in hittimes.pids.iter().enumerate(){
+            for (event,hit) in [("produced",hittimes.produced[idx]),("threshold",hittimes.threshold[idx])]{
+                let (time,censored) = match hit { Some(time) => (time,0), None => (hittimes.endtime,1) };
+                self.hittingfile.as_ref().unwrap().write_all(format!("{},{},{},{},{}\n",config.replid,pid,event,time,censored).as_bytes()).unwrap();
+            }
         }
+    }
 
-        //Write population file
-        if !self.popfile.is_none(){
-            self.write_popfile(config,time,pop);
+    pub fn write_hittingsummaryfile(&self, summaries: &[HittingSummary]){
+        for summary in summaries.iter(){
+            let quantiles: Vec<String> = summary.quantiles.iter().map(|q| match q { Some(time) => time.to_string(), None => String::from("NA") }).collect();
+            self.hittingsummaryfile.as_ref().unwrap().write_all(format!("{},{},{},{},{},{}\n",summary.pid,summary.event,summary.nrepl,summary.nhit,summary.restrictedmean,quantiles.join(",")).as_bytes()).unwrap();
         }
+    }
 
-        // Write altmut file
-        if !self.altmutfile.is_none(){
-            self.write_altmutfile(config,time,pop,gpmap);
+    pub fn write_mutrateenvfile(&self, config: &Config, mutrate_by_env: &HashMap<i32,(f64,u64)>){
+        let mut envids: Vec<&i32> = mutrate_by_env.keys().collect();
+        envids.sort();
+        for envid in envids{
+            let (summed,gens) = mutrate_by_env[envid];
+            self.mutrateenvfile.as_ref().unwrap().write_all(format!("{},{},{},{}\n",config.replid,envid,gens,summed/gens as f64).as_bytes()).unwrap();
         }
+    }
+
+    pub fn write_lineagefile(&self, config: &Config, lineage: &Lineage, pop: &Population, gpmap: &Gpmap){
+        // Line of descent of the dominant genotype, one row per genotype on the line (founder first)
+        let (domx,domy,_) = pop.get_dominance_info();
+        let line = lineage.get_line_of_descent(domx as u64,domy as u64);
+
+        let mut last_pid: Option<u64> = None;
+        for node in line.iter(){
+            let pid = gpmap.get_pid_at(node.x,node.y);
+            self.lineagefile.as_ref().unwrap().write_all(format!("{},{},{},{},{}\n",config.replid,node.time,node.x,node.y,pid).as_bytes()).unwrap();
 
-        // Write evolvability file
-        if !self.evolfile.is_none(){
-            self.write_evolfile(config,time,pop,gpmap);
+            // Phenotype sequence along the line of descent, one row per phenotype transition
+            if last_pid!=Some(pid){
+                self.lineagephenofile.as_ref().unwrap().write_all(format!("{},{},{}\n",config.replid,node.time,pid).as_bytes()).unwrap();
+                last_pid = Some(pid);
+            }
         }
+    }
+
+}
 
-        // Write Time average file
-        if !self.avgfile.is_none(){
-            self.write_avgfile(config, time, pop);
+
+
+fn create_csv(path: &str, header: &str) -> Result<File, Box<dyn Error>>{
+    let mut file = File::create(path)?;
+    file.write_all(header.as_bytes())?;
+    Ok(file)
+}
+
+
+pub fn builtin_observers(config: &Config, gpmap: &Gpmap) -> Result<Vec<Box<dyn Observer>>, Box<dyn Error>>{
+    // Per-generation output files, written by observers called from the run loop
+    let mut observers: Vec<Box<dyn Observer>> = Vec::new();
+
+    if config.outdom{
+        if config.outdomcomp{
+            observers.push(Box::new(DominantWriter { file: create_csv("./output/dominant.csv","repl,time,domx,domy,abundance,domcomp\n")?, components: Some(Components::new(config,gpmap)) }));
+        } else {
+            observers.push(Box::new(DominantWriter { file: create_csv("./output/dominant.csv","repl,time,domx,domy,abundance\n")?, components: None }));
+        }
+    }
+    if config.outevol{
+        observers.push(Box::new(EvolvabilityWriter { file: create_csv("./output/evolvability.csv","repl,time,popevol\n")? }));
+    }
+    if config.outenv{
+        observers.push(Box::new(EnvironmentWriter { file: create_csv("./output/environment.csv","repl,time,envid\n")? }));
+    }
+    if config.outaltmut{
+        let tablesize = config.grid_x*config.grid_y*gpmap.get_pid_list().len() as u64;
+        if tablesize>MAXALTMUTTABLE {
+            println!("Warning: skipping altmut.csv, which needs {} probabilities per mutation rate on this map (more than the limit of {})",tablesize,MAXALTMUTTABLE);
+        } else {
+            observers.push(Box::new(AltmutWriter { file: create_csv("./output/altmut.csv","repl,time,pid,probmut,lognomut,expmut\n")?, tables: HashMap::new() }));
         }
     }
+    if config.outpopsave{
+        observers.push(Box::new(PopulationWriter { file: create_csv("./output/population.csv","repl,time,x,y,pop\n")? }));
+    }
+    if config.outtimeavg{
+        observers.push(Box::new(TimeavgWriter { file: create_csv("./output/timeavg.csv","repl,x,y,pop\n")? }));
+    }
+    if config.outfitness{
+        observers.push(Box::new(FitnessWriter { file: create_csv("./output/fitness.csv","repl,time,envid,meanfit,varfit,maxfit,load\n")? }));
+    }
+    if config.outfitnessenv{
+        observers.push(Box::new(FitnessEnvWriter { file: create_csv("./output/fitnessenv.csv","repl,time,envid,meanfit\n")? }));
+    }
+    if config.outdiversity{
+        observers.push(Box::new(DiversityWriter { file: create_csv("./output/diversity.csv","repl,time,ngeno,npheno,shannongeno,simpsongeno,effgeno,shannonpheno,simpsonpheno\n")? }));
+    }
+    if config.outspatial && gpmap.get_space()==Space::Grid{
+        observers.push(Box::new(SpatialWriter { file: create_csv("./output/spatial.csv","repl,time,centx,centy,gyration,meanpairdist,displacement\n")?, startcentroid: (0.0,0.0) }));
+    }
+    if config.outpheno{
+        let every = if config.phenoevery==0 { config.saveevery } else { config.phenoevery };
+        observers.push(Box::new(PhenotypeWriter { file: create_csv("./output/phenotype.csv","repl,time,pid,count\n")?, every }));
+    }
+    if config.outmutrate && config.evolmut{
+        observers.push(Box::new(MutrateWriter { file: create_csv("./output/mutrate.csv","repl,time,envid,mutrate,count\n")? }));
+    }
 
-    pub fn write_domfile(&self,config: &Config, time:u64,pop: &Population){
-        let (domx,domy,abundance) = pop.get_dominance_info();
-        self.domfile.as_ref().unwrap().write(format!("{},{},{},{},{}\n",config.replid,time,domx,domy,abundance).as_bytes()).unwrap();
+    Ok(observers)
+}
+
+
+pub struct DominantWriter{
+    file: File,
+    components: Option<Components>, // Neutral components of the map (if the dominant genotype's component is reported)
+}
+
+impl Observer for DominantWriter{
+    fn observe(&mut self, obs: &Observation){
+        let (domx,domy,abundance) = obs.pop.get_dominance_info();
+        if let Some(components) = self.components.as_ref(){
+            let domcomp = components.get_component_at(domx as u64,domy as u64);
+            self.file.write_all(format!("{},{},{},{},{},{}\n",obs.config.replid,obs.time,domx,domy,abundance,domcomp).as_bytes()).unwrap();
+        } else {
+            self.file.write_all(format!("{},{},{},{},{}\n",obs.config.replid,obs.time,domx,domy,abundance).as_bytes()).unwrap();
+        }
     }
+}
+
+
+pub struct EnvironmentWriter{
+    file: File,
+}
 
-    pub fn write_envfile(&self,config: &Config,time: u64,envid: i32){
-        self.envfile.as_ref().unwrap().write(format!("{},{},{}\n",config.replid,time,envid).as_bytes()).unwrap();
+impl Observer for EnvironmentWriter{
+    fn observe(&mut self, obs: &Observation){
+        self.file.write_all(format!("{},{},{}\n",obs.config.replid,obs.time,obs.envid).as_bytes()).unwrap();
     }
+}
+
+
+pub struct PopulationWriter{
+    file: File,
+}
 
-    pub fn write_popfile(&self,config: &Config,time: u64,pop: &Population){
-        for idx in 0..config.grid_x{
-            for idy in 0..config.grid_y{
-                let size = pop.get_at(idx,idy);
-                if size!=0{
-                    self.popfile.as_ref().unwrap().write(
-                        format!("{},{},{},{},{}\n",config.replid,time,idx,idy,size).as_bytes()
+impl Observer for PopulationWriter{
+    fn observe(&mut self, obs: &Observation){
+        for idx in 0..obs.config.grid_x{
+            for idy in 0..obs.config.grid_y{
+                let size = obs.pop.get_at(idx,idy);
+                if size!=0.0{
+                    self.file.write_all(
+                        format!("{},{},{},{},{}\n",obs.config.replid,obs.time,idx,idy,size).as_bytes()
                     ).unwrap();
                 }
             }
         }
     }
+}
 
-    pub fn write_evolfile(&self, config: &Config, time: u64, pop: &Population,gpmap: &Gpmap){
-        let mut total_evol: f64 = 0.0;
 
-        for idx in 0..config.grid_x{
-            for idy in 0..config.grid_y{
-                let popsize = pop.get_at(idx,idy);
-                let evol = gpmap.get_evol_at(config,idx,idy);
+pub fn get_mean_fitness(pop: &Population, gfmap: &[Vec<f64>]) -> f64{
+    // Mean fitness of the individuals in the population
+    let mut total: f64 = 0.0;
+    let mut summed: f64 = 0.0;
+    for (idx,row) in gfmap.iter().enumerate(){
+        for (idy,fitness) in row.iter().enumerate(){
+            let size = pop.get_at(idx as u64,idy as u64);
+            total += size;
+            summed += size*fitness;
+        }
+    }
+    summed/total
+}
+
 
-                total_evol += popsize as f64*evol;
+pub struct FitnessWriter{
+    file: File,
+}
 
+impl Observer for FitnessWriter{
+    fn observe(&mut self, obs: &Observation){
+        let meanfit = get_mean_fitness(obs.pop,obs.gfmap);
+
+        let mut total: f64 = 0.0;
+        let mut varfit: f64 = 0.0;
+        let mut maxfit: f64 = 0.0; // Highest fitness of any genotype in the current environment
+        for (idx,row) in obs.gfmap.iter().enumerate(){
+            for (idy,fitness) in row.iter().enumerate(){
+                let size = obs.pop.get_at(idx as u64,idy as u64);
+                total += size;
+                varfit += size*(fitness-meanfit).powi(2);
+                maxfit = maxfit.max(*fitness);
             }
         }
+        varfit /= total;
+        let load = if maxfit>0.0 { 1.0-meanfit/maxfit } else { f64::NAN };
 
-        total_evol /= config.popsize as f64;
+        self.file.write_all(format!("{},{},{},{},{},{},{}\n",obs.config.replid,obs.time,obs.envid,meanfit,varfit,maxfit,load).as_bytes()).unwrap();
+    }
+}
 
-        self.evolfile.as_ref().unwrap().write(format!("{},{},{}\n",config.replid,time,total_evol).as_bytes()).unwrap();
+
+pub struct FitnessEnvWriter{
+    file: File,
+}
+
+impl Observer for FitnessEnvWriter{
+    fn observe(&mut self, obs: &Observation){
+        // Mean fitness the current population would have in each defined environment
+        for (envid,pfmap) in obs.env.get_pfmaps().iter().enumerate(){
+            let meanfit = get_mean_fitness(obs.pop,&obs.gpmap.get_gfmap(pfmap));
+            self.file.write_all(format!("{},{},{},{}\n",obs.config.replid,obs.time,envid,meanfit).as_bytes()).unwrap();
+        }
     }
+}
+
 
-    pub fn write_altmutfile(&self, config: &Config, time: u64, pop: &Population, gpmap: &Gpmap){
+fn get_diversity(counts: &[f64]) -> (usize,f64,f64){
+    // Number of occupied classes, Shannon diversity and Simpson diversity (probability that two random individuals differ)
+    let total: f64 = counts.iter().sum();
+    let occupied: Vec<f64> = counts.iter().filter(|c| **c>0.0).map(|c| c/total).collect();
+    let shannon: f64 = occupied.iter().map(|p| -p*p.ln()).sum::<f64>()+0.0; // Adding 0 turns -0 (a single class) into 0
+    let simpson: f64 = 1.0-occupied.iter().map(|p| p*p).sum::<f64>();
+    (occupied.len(),shannon,simpson)
+}
 
-        let pid_list: &Vec<u64> = gpmap.get_pid_list();
 
-        for pid in pid_list.iter(){
-            let mut total_prob = 1.0; // Probability of not changing
+pub struct DiversityWriter{
+    file: File,
+}
+
+impl Observer for DiversityWriter{
+    fn observe(&mut self, obs: &Observation){
+        let genocounts: Vec<f64> = (0..obs.config.grid_x).flat_map(|idx| (0..obs.config.grid_y).map(move |idy| (idx,idy))).map(|(idx,idy)| obs.pop.get_at(idx,idy)).collect();
+        let phenocounts: Vec<f64> = obs.pop.get_pheno_counts(obs.gpmap).iter().map(|(_,c)| *c).collect();
+
+        let (ngeno,shannongeno,simpsongeno) = get_diversity(&genocounts);
+        let (npheno,shannonpheno,simpsonpheno) = get_diversity(&phenocounts);
+        let effgeno = shannongeno.exp(); // Effective number of genotypes
+
+        self.file.write_all(format!("{},{},{},{},{},{},{},{},{}\n",obs.config.replid,obs.time,ngeno,npheno,shannongeno,simpsongeno,effgeno,shannonpheno,simpsonpheno).as_bytes()).unwrap();
+    }
+}
 
-            for idx in 0..config.grid_x{
-                for idy in 0..config.grid_y{
-                    let popsize = pop.get_at(idx,idy);
-                    let prob = gpmap.get_mutprob(config,*pid,idx,idy);
 
-                    total_prob *= ((1.00-prob).powi(popsize as i32)*((10.0 as f64).powi(10))).round()/(10.0 as f64).powi(10);
+fn get_circular_mean(counts: &[f64]) -> f64{
+    // Centroid of abundances along a periodic axis (positions mapped to angles on a circle)
+    let size = counts.len() as f64;
+    let (mut cossum,mut sinsum) = (0.0,0.0);
+    for (pos,count) in counts.iter().enumerate(){
+        let angle = 2.0*std::f64::consts::PI*pos as f64/size;
+        cossum += count*angle.cos();
+        sinsum += count*angle.sin();
+    }
+    sinsum.atan2(cossum).rem_euclid(2.0*std::f64::consts::PI)*size/(2.0*std::f64::consts::PI)
+}
+
+
+pub struct SpatialWriter{
+    file: File,
+    startcentroid: (f64,f64), // Centroid at generation 0 of the current run
+}
+
+impl Observer for SpatialWriter{
+    fn observe(&mut self, obs: &Observation){
+        let (grid_x,grid_y) = (obs.config.grid_x,obs.config.grid_y);
+
+        // Abundances summed along each axis
+        let mut xcounts: Vec<f64> = vec![0.0; grid_x as usize];
+        let mut ycounts: Vec<f64> = vec![0.0; grid_y as usize];
+        for idx in 0..grid_x{
+            for idy in 0..grid_y{
+                let size = obs.pop.get_at(idx,idy);
+                xcounts[idx as usize] += size;
+                ycounts[idy as usize] += size;
+            }
+        }
+        let total: f64 = xcounts.iter().sum();
+
+        let centroid = (get_circular_mean(&xcounts),get_circular_mean(&ycounts));
+        if obs.time==0 {
+            self.startcentroid = centroid;
+        }
+
+        // Radius of gyration around the centroid
+        let mut gyration: f64 = 0.0;
+        for idx in 0..grid_x{
+            for idy in 0..grid_y{
+                let size = obs.pop.get_at(idx,idy);
+                if size!=0.0{
+                    let dx = gpm::get_wrapped_delta(centroid.0,idx as f64,grid_x as f64);
+                    let dy = gpm::get_wrapped_delta(centroid.1,idy as f64,grid_y as f64);
+                    gyration += size*(dx*dx+dy*dy);
                 }
             }
+        }
+        gyration = (gyration/total).sqrt();
+
+        // Mean number of mutational steps between two distinct individuals (the x and y steps only depend on the axis sums)
+        let mut pairdist: f64 = 0.0;
+        for (counts,size) in [(&xcounts,grid_x),(&ycounts,grid_y)]{
+            for (from,nfrom) in counts.iter().enumerate(){
+                for (to,nto) in counts.iter().enumerate(){
+                    if *nfrom!=0.0 && *nto!=0.0 {
+                        pairdist += nfrom*nto*gpm::get_torus_dist(from as u64,to as u64,size) as f64;
+                    }
+                }
+            }
+        }
+        let meanpairdist = pairdist/(total*(total-1.0));
+
+        let dx = gpm::get_wrapped_delta(self.startcentroid.0,centroid.0,grid_x as f64);
+        let dy = gpm::get_wrapped_delta(self.startcentroid.1,centroid.1,grid_y as f64);
+        let displacement = (dx*dx+dy*dy).sqrt();
+
+        self.file.write_all(format!("{},{},{},{},{},{},{}\n",obs.config.replid,obs.time,centroid.0,centroid.1,gyration,meanpairdist,displacement).as_bytes()).unwrap();
+    }
+}
 
-            
-            total_prob = 1.00 - total_prob;
 
-            self.altmutfile.as_ref().unwrap().write(format!("{},{},{},{:.10}\n",config.replid,time,*pid,total_prob).as_bytes()).unwrap();
+pub struct PhenotypeWriter{
+    file: File,
+    every: u64, // Generations between outputs
+}
+
+impl Observer for PhenotypeWriter{
+    fn observe(&mut self, obs: &Observation){
+        if obs.time.is_multiple_of(self.every) || obs.last {
+            for (pid,count) in obs.pop.get_pheno_counts(obs.gpmap){
+                self.file.write_all(format!("{},{},{},{}\n",obs.config.replid,obs.time,pid,count).as_bytes()).unwrap();
+            }
         }
     }
 
-    pub fn write_avgfile(&self,config: &Config, time: u64, pop: &Population){
-        if time==config.timeavgend{ // This condition requires that generate_output is called at the time step "timeavgend", otherwise no time average will be created!
-            for idx in 0..config.grid_x{
-                for idy in 0..config.grid_y{
-                    let size = pop.avg_get_at(idx,idy);
+    fn every_generation(&self) -> bool {
+        true
+    }
+}
+
+
+pub struct EvolvabilityWriter{
+    file: File,
+}
+
+impl Observer for EvolvabilityWriter{
+    fn observe(&mut self, obs: &Observation){
+        let mut total_evol: f64 = 0.0;
+
+        for idx in 0..obs.config.grid_x{
+            for idy in 0..obs.config.grid_y{
+                let popsize = obs.pop.get_at(idx,idy);
+                if popsize==0.0 {
+                    continue;
+                }
+                let evol = obs.gpmap.get_evol_at(idx,idy);
+
+                total_evol += popsize*evol;
+
+            }
+        }
+
+        total_evol /= obs.config.popsize as f64;
+
+        self.file.write_all(format!("{},{},{}\n",obs.config.replid,obs.time,total_evol).as_bytes()).unwrap();
+    }
+}
+
+
+pub struct AltmutWriter{
+    file: File,
+    tables: HashMap<u64,Vec<Vec<Vec<f64>>>>, // Probability of mutating to each phenotype from every genotype, one table per mutation rate (bits)
+}
+
+impl Observer for AltmutWriter{
+    fn observe(&mut self, obs: &Observation){
+
+        let pid_list: &Vec<u64> = obs.gpmap.get_pid_list();
+        let mut lognomuts: Vec<f64> = vec![0.0; pid_list.len()]; // Log probability that no offspring has the phenotype
+        let mut expmuts: Vec<f64> = vec![0.0; pid_list.len()]; // Expected number of offspring with the phenotype
+
+        // Only occupied classes can produce mutants
+        for (idx,idy,level,popsize) in obs.pop.get_classes().iter(){
+            let mutrate = obs.pop.get_mutrate(*level);
+            let table = self.tables.entry(mutrate.to_bits()).or_insert_with(|| obs.gpmap.get_mutprob_table(mutrate));
+            let probs = &table[*idx as usize][*idy as usize];
+
+            for (pidx,prob) in probs.iter().enumerate(){
+                lognomuts[pidx] += popsize*(-prob.min(1.0)).ln_1p(); // The minimum only guards against rounding above 1
+                expmuts[pidx] += popsize*prob;
+            }
+        }
+
+        for (pidx,pid) in pid_list.iter().enumerate(){
+            let probmut = -lognomuts[pidx].exp_m1(); // Probability of at least one such offspring
+            self.file.write_all(format!("{},{},{},{:e},{:e},{:e}\n",obs.config.replid,obs.time,*pid,probmut,lognomuts[pidx],expmuts[pidx]).as_bytes()).unwrap();
+        }
+    }
+}
+
+
+pub struct MutrateWriter{
+    file: File,
+}
+
+impl Observer for MutrateWriter{
+    fn observe(&mut self, obs: &Observation){
+        let counts = obs.pop.get_mutrate_counts();
+        for (mutrate,count) in obs.pop.get_mutlevels().iter().zip(counts.iter()){
+            if *count!=0.0{
+                self.file.write_all(format!("{},{},{},{},{}\n",obs.config.replid,obs.time,obs.envid,mutrate,count).as_bytes()).unwrap();
+            }
+        }
+    }
+}
+
+
+pub struct TimeavgWriter{
+    file: File,
+}
+
+impl Observer for TimeavgWriter{
+    fn observe(&mut self, obs: &Observation){
+        if obs.time==obs.config.timeavgend{ // This condition requires that the observer is called at the time step "timeavgend", otherwise no time average will be created!
+            for idx in 0..obs.config.grid_x{
+                for idy in 0..obs.config.grid_y{
+                    let size = obs.pop.avg_get_at(idx,idy);
                     if size!=0.0{
-                        self.avgfile.as_ref().unwrap().write(
-                            format!("{},{},{},{}\n",config.replid,idx,idy,size).as_bytes()
+                        self.file.write_all(
+                            format!("{},{},{},{}\n",obs.config.replid,idx,idy,size).as_bytes()
                         ).unwrap();
                     }
                 }
             }
         }
     }
+}
+
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn diversity_of_one_class(){
+        let (noccupied,shannon,simpson) = get_diversity(&[0.0,5.0,0.0]);
+        assert_eq!(noccupied,1);
+        assert!(shannon==0.0 && shannon.is_sign_positive());
+        assert_eq!(format!("{},{}",shannon,simpson),"0,0");
+    }
 
+    #[test]
+    fn diversity_of_even_classes(){
+        let (noccupied,shannon,simpson) = get_diversity(&[2.0,2.0,0.0,2.0,2.0]);
+        assert_eq!(noccupied,4);
+        assert!((shannon-4.0_f64.ln()).abs()<1e-12);
+        assert!((simpson-0.75).abs()<1e-12);
+    }
 }
@@ -1,37 +1,58 @@
 use std::error::Error;
+use std::cmp;
 use crate::config::Config;
 use crate::gpm::Gpmap;
-use rand::{distributions::{Distribution, Uniform}};
+use rand::{distributions::{Distribution, Uniform, WeightedIndex}};
+use rand::seq::SliceRandom;
+use std::fs;
+use std::collections::HashMap;
 
 
 
 #[derive(Clone)]
 pub struct Population{
-    pop: Vec<Vec<u64>>,
-    avgpop: Vec<Vec<f64>>
+    pop: Vec<Vec<f64>>, // Abundance of each genotype (fractional in the deterministic mode)
+    avgpop: Vec<Vec<f64>>,
+    modpop: Option<HashMap<(u64,u64,usize),u64>>, // Abundance of each (x,y,mutation rate level) class if mutation rates evolve
+    mutlevels: Vec<f64> // Mutation probability of each modifier level (only one level if mutation rates do not evolve)
 }
 
 
 
 impl Population{
 
+    pub fn generate(cfg:&Config,gpmap:&Gpmap) -> Result<Population,Box<dyn Error>>{
+        // Create the initial population using the strategy chosen with --initpop
+        match cfg.initpop.as_str(){
+            "uniform" => Population::gen_uniform(cfg),
+            "randpheno" => Population::gen_initpop(cfg,gpmap),
+            "coords" => Population::gen_at_xy(cfg),
+            "unifpheno" => Population::gen_uniform_pheno(cfg,gpmap),
+            "file" => Population::load_initpop(cfg),
+            _ => Err(format!("unknown initial population strategy {}",cfg.initpop).into()),
+        }
+    }
+
     pub fn gen_initpop(cfg:&Config,gpmap:&Gpmap) -> Result<Population,Box<dyn Error>>{
-        let mut popvec:Vec<Vec<u64>> = vec![vec![0; cfg.grid_y as usize]; cfg.grid_x as usize]; // Create an empty population
+        let mut popvec:Vec<Vec<f64>> = vec![vec![0.0; cfg.grid_y as usize]; cfg.grid_x as usize]; // Create an empty population
         let avgpopvec:Vec<Vec<f64>> = vec![vec![0.0; cfg.grid_y as usize]; cfg.grid_x as usize]; // Create an empty averaged population
 
         // Generate initial population based on popsize, and rndstrtpheno
         // Pick a random xy from rndstrtpheno genotypes
+        if gpmap.get_matching_xys(&cfg.rndstrtpheno).is_empty(){
+            return Err(format!("no genotypes with phenotypes {:?}",cfg.rndstrtpheno).into());
+        }
         let (randx,randy) = gpmap.get_random_xy(&cfg.rndstrtpheno);
 
         // Set popvec at rand x y to popsize
-        popvec[randx as usize][randy as usize] = cfg.popsize;
+        popvec[randx as usize][randy as usize] = cfg.popsize as f64;
 
-        let pop: Population = Population { pop: popvec, avgpop: avgpopvec };
+        let pop: Population = Population::with_modifiers(cfg, popvec, avgpopvec);
         Ok(pop)
     }
 
     pub fn gen_uniform(cfg: &Config) -> Result<Population, Box<dyn Error>>{ 
-        let mut popvec: Vec<Vec<u64>> = vec![vec![0; cfg.grid_y as usize]; cfg.grid_x as usize]; // Create empty
+        let mut popvec: Vec<Vec<f64>> = vec![vec![0.0; cfg.grid_y as usize]; cfg.grid_x as usize]; // Create empty
         let avgpopvec:Vec<Vec<f64>> = vec![vec![0.0; cfg.grid_y as usize]; cfg.grid_x as usize]; // Create an empty averaged population
 
         let dist_x = Uniform::from(0..cfg.grid_x as usize);
@@ -39,18 +60,199 @@ impl Population{
 
         let mut rng = rand::thread_rng();
         for _ in 0..cfg.popsize{
-            popvec[dist_x.sample(&mut rng)][dist_y.sample(&mut rng)] += 1;
+            popvec[dist_x.sample(&mut rng)][dist_y.sample(&mut rng)] += 1.0;
+        }
+
+        let pop: Population = Population::with_modifiers(cfg, popvec, avgpopvec);
+        Ok(pop)
+    }
+
+    pub fn gen_at_xy(cfg: &Config) -> Result<Population, Box<dyn Error>>{
+        let mut popvec: Vec<Vec<f64>> = vec![vec![0.0; cfg.grid_y as usize]; cfg.grid_x as usize]; // Create empty
+        let avgpopvec:Vec<Vec<f64>> = vec![vec![0.0; cfg.grid_y as usize]; cfg.grid_x as usize]; // Create an empty averaged population
+
+        if cfg.initxy.len()!=2 || cfg.initxy[0]>=cfg.grid_x || cfg.initxy[1]>=cfg.grid_y {
+            return Err(format!("initxy {:?} should be an x and y coordinate inside the grid",cfg.initxy).into());
+        }
+
+        // Everyone starts at the given genotype
+        popvec[cfg.initxy[0] as usize][cfg.initxy[1] as usize] = cfg.popsize as f64;
+
+        let pop: Population = Population::with_modifiers(cfg, popvec, avgpopvec);
+        Ok(pop)
+    }
+
+    pub fn gen_uniform_pheno(cfg: &Config, gpmap: &Gpmap) -> Result<Population, Box<dyn Error>>{
+        let mut popvec: Vec<Vec<f64>> = vec![vec![0.0; cfg.grid_y as usize]; cfg.grid_x as usize]; // Create empty
+        let avgpopvec:Vec<Vec<f64>> = vec![vec![0.0; cfg.grid_y as usize]; cfg.grid_x as usize]; // Create an empty averaged population
+
+        let all_matching_xys = gpmap.get_matching_xys(&cfg.rndstrtpheno);
+        if all_matching_xys.is_empty(){
+            return Err(format!("no genotypes with phenotypes {:?}",cfg.rndstrtpheno).into());
+        }
+
+        // Spread individuals uniformly over the genotypes of the given phenotypes
+        let mut rng = rand::thread_rng();
+        for _ in 0..cfg.popsize{
+            let (x,y) = all_matching_xys.choose(&mut rng).unwrap();
+            popvec[*x as usize][*y as usize] += 1.0;
+        }
+
+        let pop: Population = Population::with_modifiers(cfg, popvec, avgpopvec);
+        Ok(pop)
+    }
+
+    pub fn load_initpop(cfg: &Config) -> Result<Population, Box<dyn Error>>{
+        let mut popvec: Vec<Vec<f64>> = vec![vec![0.0; cfg.grid_y as usize]; cfg.grid_x as usize]; // Create empty
+        let avgpopvec:Vec<Vec<f64>> = vec![vec![0.0; cfg.grid_y as usize]; cfg.grid_x as usize]; // Create an empty averaged population
+
+        // Population files are either headerless rows of x,y,pop or have a header naming the x, y and pop columns
+        // (optionally with repl and time columns, as in population.csv)
+        let popcontent = fs::read_to_string(&cfg.initfilepath)?;
+        let mut lines = popcontent.lines().enumerate().peekable();
+
+        let mut columns: Vec<String> = vec![String::from("x"),String::from("y"),String::from("pop")];
+        if let Some((_,header)) = lines.peek(){
+            if header.split(",").next().unwrap().trim().parse::<f64>().is_err(){
+                columns = header.split(",").map(|v| v.trim().to_string()).collect();
+                lines.next();
+            }
+        }
+        let colidx = |name: &str| columns.iter().position(|c| c==name);
+        let (xcol,ycol,popcol) = match (colidx("x"),colidx("y"),colidx("pop")){
+            (Some(xcol),Some(ycol),Some(popcol)) => (xcol,ycol,popcol),
+            _ => return Err("population file should have x, y and pop columns".into()),
+        };
+        let replcol = colidx("repl");
+        let timecol = colidx("time");
+
+        let mut rows: Vec<(u64,u64,u64,u64,f64)> = Vec::new(); // (repl,time,x,y,pop)
+        for (lineno,row) in lines{
+            let row_as_vec: Vec<&str> = row.split(",").map(|v| v.trim()).collect();
+            if row_as_vec.len()!=columns.len() {
+                return Err(format!("line {} of the population file should have {} columns",lineno+1,columns.len()).into());
+            }
+            let repl = match replcol { Some(col) => row_as_vec[col].parse::<u64>()?, None => cfg.initrepl };
+            let time = match timecol { Some(col) => row_as_vec[col].parse::<u64>()?, None => 0 };
+            let x = row_as_vec[xcol].parse::<u64>()?;
+            let y = row_as_vec[ycol].parse::<u64>()?;
+            let size = row_as_vec[popcol].parse::<f64>()?;
+            if x>=cfg.grid_x || y>=cfg.grid_y {
+                return Err(format!("line {} of the population file is outside the grid",lineno+1).into());
+            }
+            rows.push((repl,time,x,y,size));
+        }
+
+        // Select the snapshot of the requested replicate at the requested time (the last one if inittime is negative)
+        rows.retain(|row| row.0==cfg.initrepl);
+        let time = if cfg.inittime<0 {
+            rows.iter().map(|row| row.1).max().ok_or(format!("population file has no rows for replicate {}",cfg.initrepl))?
+        } else {
+            cfg.inittime as u64
+        };
+        rows.retain(|row| row.1==time);
+        if rows.is_empty(){
+            return Err(format!("population file has no rows for replicate {} at time {}",cfg.initrepl,time).into());
         }
 
-        let pop: Population = Population { pop: popvec, avgpop: avgpopvec };
+        for (_,_,x,y,size) in rows.iter(){
+            popvec[*x as usize][*y as usize] += size;
+        }
+
+        // Only the meanfield mode works with fractional abundances, other modes need whole individuals
+        let total: f64 = popvec.iter().map(|a| a.iter().sum::<f64>()).sum();
+        let fractional = cfg.mode!="meanfield" && popvec.iter().flatten().any(|size| size.fract()!=0.0);
+        if (total-cfg.popsize as f64).abs()>1e-6 || fractional {
+            if !cfg.initrescale {
+                if fractional {
+                    return Err(format!("population file has fractional abundances, which only the meanfield mode accepts (use --initrescale true to round them to {} individuals)",cfg.popsize).into());
+                }
+                return Err(format!("population file has {} individuals but popsize is {} (use --initrescale true to rescale)",total,cfg.popsize).into());
+            }
+            popvec = Population::rescale(&popvec,cfg.popsize)?;
+        }
+
+        let pop: Population = Population::with_modifiers(cfg, popvec, avgpopvec);
         Ok(pop)
     }
 
-    pub fn get_dominance_info(&self) -> (usize,usize,u64){
+    fn rescale(popvec: &[Vec<f64>], popsize: u64) -> Result<Vec<Vec<f64>>, Box<dyn Error>>{
+        // Scale abundances to popsize individuals, giving leftover individuals to the largest remainders
+        let total: f64 = popvec.iter().map(|a| a.iter().sum::<f64>()).sum();
+        if total<=0.0 {
+            return Err("cannot rescale an empty population".into());
+        }
+
+        let mut scaled: Vec<Vec<f64>> = popvec.iter().map(|a| a.iter().map(|b| (b*popsize as f64/total).floor()).collect()).collect();
+        let assigned: f64 = scaled.iter().map(|a| a.iter().sum::<f64>()).sum();
+
+        let mut remainders: Vec<(f64,usize,usize)> = Vec::new();
+        for (idx,row) in popvec.iter().enumerate(){
+            for (idy,size) in row.iter().enumerate(){
+                remainders.push((size*popsize as f64/total-scaled[idx][idy],idx,idy));
+            }
+        }
+        remainders.sort_by(|a,b| b.0.partial_cmp(&a.0).unwrap());
+
+        for (_,idx,idy) in remainders.iter().take((popsize as f64-assigned) as usize){
+            scaled[*idx][*idy] += 1.0;
+        }
+
+        Ok(scaled)
+    }
+
+    pub fn from_grid(cfg: &Config, popvec: Vec<Vec<f64>>) -> Result<Population, Box<dyn Error>>{
+        // Population given directly as abundances indexed by x and y
+        if popvec.len()!=cfg.grid_x as usize || popvec.iter().any(|row| row.len()!=cfg.grid_y as usize) {
+            return Err(format!("the population should be a {} by {} grid",cfg.grid_x,cfg.grid_y).into());
+        }
+        if popvec.iter().flatten().any(|size| *size<0.0) {
+            return Err("the population should not have negative abundances".into());
+        }
+        if cfg.mode!="meanfield" && popvec.iter().flatten().any(|size| size.fract()!=0.0) {
+            return Err("the population should have whole abundances outside the meanfield mode".into());
+        }
+        let avgpopvec:Vec<Vec<f64>> = vec![vec![0.0; cfg.grid_y as usize]; cfg.grid_x as usize]; // Create an empty averaged population
+
+        let pop: Population = Population::with_modifiers(cfg, popvec, avgpopvec);
+        Ok(pop)
+    }
+
+    fn with_modifiers(cfg: &Config, popvec: Vec<Vec<f64>>, avgpopvec: Vec<Vec<f64>>) -> Population{
+        if !cfg.evolmut {
+            return Population { pop: popvec, avgpop: avgpopvec, modpop: None, mutlevels: vec![cfg.mutprob] };
+        }
+
+        // Modifier levels are spaced evenly on a log scale between mutmin and mutmax
+        let nlevels = cmp::max(cfg.mutlevels,1) as usize;
+        let mutlevels: Vec<f64> = (0..nlevels).map(|i| {
+            if nlevels==1 { cfg.mutmin } else { cfg.mutmin*(cfg.mutmax/cfg.mutmin).powf(i as f64/(nlevels-1) as f64) }
+        }).collect();
+
+        // Everyone starts at the level closest to mutprob
+        let startlevel = (0..nlevels).min_by(|a,b| {
+            let da = (mutlevels[*a].ln()-cfg.mutprob.ln()).abs();
+            let db = (mutlevels[*b].ln()-cfg.mutprob.ln()).abs();
+            da.partial_cmp(&db).unwrap()
+        }).unwrap();
+
+        let mut modpop: HashMap<(u64,u64,usize),u64> = HashMap::new();
+        for (idx,row) in popvec.iter().enumerate(){
+            for (idy,size) in row.iter().enumerate(){
+                if *size!=0.0{
+                    modpop.insert((idx as u64,idy as u64,startlevel),size.round() as u64); // Abundances are whole outside the meanfield mode
+                }
+            }
+        }
+
+        Population { pop: popvec, avgpop: avgpopvec, modpop: Some(modpop), mutlevels }
+    }
+
+    pub fn get_dominance_info(&self) -> (usize,usize,f64){
 
         let mut max_x:usize = 0;
         let mut max_y:usize = 0;
-        let mut max_abund:u64 = 0;
+        let mut max_abund:f64 = 0.0;
 
         for (idx,row) in self.pop.iter().enumerate(){
             for (idy,popincell) in row.iter().enumerate(){
@@ -65,7 +267,24 @@ impl Population{
         (max_x,max_y,max_abund)
     }
 
-    pub fn get_at(&self,x: u64, y: u64) -> u64{
+    pub fn get_pheno_counts(&self, gpmap: &Gpmap) -> Vec<(u64,f64)>{
+        // Abundance of every phenotype in the map (in the order of the map's phenotype list)
+        let pid_list = gpmap.get_pid_list();
+        let mut counts: Vec<f64> = vec![0.0; pid_list.len()];
+
+        for (idx,row) in self.pop.iter().enumerate(){
+            for (idy,size) in row.iter().enumerate(){
+                if *size!=0.0{
+                    let pid = gpmap.get_pid_at(idx as u64,idy as u64);
+                    counts[pid_list.iter().position(|p| *p==pid).unwrap()] += size;
+                }
+            }
+        }
+
+        pid_list.iter().copied().zip(counts).collect()
+    }
+
+    pub fn get_at(&self,x: u64, y: u64) -> f64{
         self.pop[x as usize][y as usize]
     }
 
@@ -73,13 +292,84 @@ impl Population{
         self.avgpop[x as usize][y as usize]
     }
 
-    pub fn set_pop(&mut self, new_pop: Vec<Vec<u64>>){
+    pub fn set_pop(&mut self, new_pop: Vec<Vec<f64>>){
         self.pop = new_pop;
     }
 
+    pub fn set_monomorphic(&mut self, x: u64, y: u64, popsize: u64){
+        // Put the whole population on a single genotype
+        self.pop.iter_mut().for_each(|a| a.iter_mut().for_each(|b| *b = 0.0));
+        self.pop[x as usize][y as usize] = popsize as f64;
+    }
+
+    pub fn introduce_mutants(&mut self, x: u64, y: u64, k: u64){
+        // Remove k individuals at random and add k individuals at x,y
+        let mut rng = rand::thread_rng();
+        for _ in 0..k{
+            let cells: Vec<(usize,usize)> = (0..self.pop.len()).flat_map(|idx| (0..self.pop[idx].len()).map(move |idy| (idx,idy))).collect();
+            let weights: Vec<f64> = cells.iter().map(|(idx,idy)| self.pop[*idx][*idy]).collect();
+            let (idx,idy) = cells[WeightedIndex::new(&weights).unwrap().sample(&mut rng)];
+            self.pop[idx][idy] -= 1.0;
+        }
+        self.pop[x as usize][y as usize] += k as f64;
+    }
+
+    pub fn get_classes(&self) -> Vec<(u64,u64,usize,f64)>{
+        // Returns (x,y,mutation rate level,abundance) for every occupied class
+        let mut classes: Vec<(u64,u64,usize,f64)> = Vec::new();
+
+        if let Some(modpop) = self.modpop.as_ref(){
+            classes = modpop.iter().map(|((x,y,level),size)| (*x,*y,*level,*size as f64)).collect();
+            classes.sort_by_key(|(x,y,level,_)| (*x,*y,*level));
+        } else {
+            for (idx,row) in self.pop.iter().enumerate(){
+                for (idy,size) in row.iter().enumerate(){
+                    if *size!=0.0{
+                        classes.push((idx as u64,idy as u64,0,*size));
+                    }
+                }
+            }
+        }
+
+        classes
+    }
+
+    pub fn set_modpop(&mut self, new_modpop: HashMap<(u64,u64,usize),u64>){
+        if self.modpop.is_some(){
+            self.modpop = Some(new_modpop);
+        }
+    }
+
+    pub fn evolving_mutrates(&self) -> bool{
+        self.modpop.is_some()
+    }
+
+    pub fn get_mutrate(&self, level: usize) -> f64{
+        self.mutlevels[level]
+    }
+
+    pub fn get_mutlevels(&self) -> &Vec<f64>{
+        &self.mutlevels
+    }
+
+    pub fn get_mutrate_counts(&self) -> Vec<f64>{
+        // Number of individuals at each mutation rate level
+        let mut counts: Vec<f64> = vec![0.0; self.mutlevels.len()];
+        for (_,_,level,size) in self.get_classes(){
+            counts[level] += size;
+        }
+        counts
+    }
+
+    pub fn get_mean_mutrate(&self) -> f64{
+        let counts = self.get_mutrate_counts();
+        let total: f64 = counts.iter().sum();
+        counts.iter().zip(self.mutlevels.iter()).map(|(c,m)| c*m).sum::<f64>()/total
+    }
+
     pub fn add_to_average(&mut self, config: &Config, time: u64){
         if time==config.timeavgstart {
-            self.avgpop = self.pop.iter().map(|a| a.iter().map(|&b| b as f64).collect()).collect();
+            self.avgpop = self.pop.clone();
         }
         if time>config.timeavgstart {
             let t_elapsed = time-config.timeavgstart;
@@ -88,7 +378,7 @@ impl Population{
             // Add current pop to avg
             for idx in 0..config.grid_x{
                 for idy in 0..config.grid_y{ 
-                    self.avgpop[idx as usize][idy as usize]+= self.pop[idx as usize][idy as usize] as f64; // Add current population to the summed average
+                    self.avgpop[idx as usize][idy as usize]+= self.pop[idx as usize][idy as usize]; // Add current population to the summed average
                 }
             }
 
@@ -0,0 +1,88 @@
+pub const BASES: [char;4] = ['A','C','G','U']; // Letters of RNA sequences (in the order of their indices)
+const MINLOOP: usize = 3; // Minimum number of unpaired bases closed by a hairpin
+
+
+
+pub fn can_pair(a: usize, b: usize) -> bool{
+    // Watson-Crick (AU, GC) and wobble (GU) pairs
+    matches!((BASES[a],BASES[b]),('A','U') | ('U','A') | ('G','C') | ('C','G') | ('G','U') | ('U','G'))
+}
+
+
+pub fn fold(seq: &[usize]) -> String{
+    // Nussinov secondary structure maximising the number of base pairs, in dot-bracket notation
+    let len = seq.len();
+    if len==0 {
+        return String::new();
+    }
+
+    // npairs[i*len+j] is the maximum number of pairs between bases i and j (inclusive)
+    let mut npairs: Vec<u32> = vec![0; len*len];
+    for span in MINLOOP+1..len{
+        for i in 0..len-span{
+            let j = i+span;
+            let mut best = npairs[i*len+j-1]; // j unpaired
+            for k in i..j-MINLOOP{
+                if can_pair(seq[k],seq[j]) {
+                    let left = if k>i { npairs[i*len+k-1] } else { 0 };
+                    best = best.max(left+1+npairs[(k+1)*len+j-1]);
+                }
+            }
+            npairs[i*len+j] = best;
+        }
+    }
+
+    // Traceback, leaving j unpaired when possible and otherwise pairing it with the leftmost partner
+    let mut structure: Vec<char> = vec!['.'; len];
+    let mut stack: Vec<(usize,usize)> = vec![(0,len-1)];
+    while let Some((i,j)) = stack.pop(){
+        if j<i+MINLOOP+1 {
+            continue;
+        }
+        let value = npairs[i*len+j];
+        if value==npairs[i*len+j-1] {
+            stack.push((i,j-1));
+            continue;
+        }
+        for k in i..j-MINLOOP{
+            if can_pair(seq[k],seq[j]) {
+                let left = if k>i { npairs[i*len+k-1] } else { 0 };
+                if left+1+npairs[(k+1)*len+j-1]==value {
+                    structure[k] = '(';
+                    structure[j] = ')';
+                    if k>i {
+                        stack.push((i,k-1));
+                    }
+                    stack.push((k+1,j-1));
+                    break;
+                }
+            }
+        }
+    }
+
+    structure.into_iter().collect()
+}
+
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn get_seq(letters: &str) -> Vec<usize>{
+        letters.chars().map(|c| BASES.iter().position(|b| *b==c).unwrap()).collect()
+    }
+
+    #[test]
+    fn hairpin(){
+        assert_eq!(fold(&get_seq("GGGAAACCC")),"(((...)))");
+        assert_eq!(fold(&get_seq("GGGAAAUCC")),"(((...)))"); // GU wobble pair
+    }
+
+    #[test]
+    fn minimum_loop(){
+        // Pairs need at least MINLOOP unpaired bases between them
+        assert_eq!(fold(&get_seq("GAAC")),"....");
+        assert_eq!(fold(&get_seq("GGAACC")),"(...)."); // Ties leave the last base unpaired
+        assert_eq!(fold(&get_seq("AAAAAA")),"......");
+    }
+}